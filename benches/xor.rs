@@ -0,0 +1,64 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rateless_iblt::{Encoder, Item, Symbol};
+
+/// XORs byte by byte, as a baseline for the word-wide XOR used by [`Item::xor`].
+fn xor_bytewise(a: &mut [u8], b: &[u8]) {
+    for (a, b) in core::iter::zip(a, b) {
+        *a ^= *b;
+    }
+}
+
+fn bench_size<const N: usize>(c: &mut Criterion) {
+    let a = [0x5au8; N];
+    let b = [0xa5u8; N];
+
+    let mut group = c.benchmark_group("xor");
+    group.throughput(Throughput::Bytes(N as u64));
+    group.bench_function(BenchmarkId::new("bytewise", N), |bench| {
+        let mut a = a;
+        bench.iter(|| xor_bytewise(black_box(&mut a), black_box(&b)))
+    });
+    group.bench_function(BenchmarkId::new("word", N), |bench| {
+        let mut a = a;
+        bench.iter(|| black_box(&mut a).xor(black_box(&b)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("symbol_add");
+    group.throughput(Throughput::Bytes(N as u64));
+    let symbols: Vec<Symbol<[u8; N]>> = Encoder::from_iter((0..64u8).map(|i| [i; N]))
+        .into_iter()
+        .take(64)
+        .collect();
+    group.bench_function(BenchmarkId::from_parameter(N), |bench| {
+        bench.iter(|| {
+            let mut sum = Symbol::default();
+            for s in black_box(&symbols) {
+                sum += *s;
+            }
+            sum
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("encode");
+    group.throughput(Throughput::Elements(1000));
+    let encoder = Encoder::from_iter((0..1000u32).map(|i| {
+        let mut item = [0u8; N];
+        item[..4].copy_from_slice(&i.to_le_bytes());
+        item
+    }));
+    group.bench_function(BenchmarkId::from_parameter(N), |bench| {
+        bench.iter(|| encoder.clone().into_iter().next_chunk(1000))
+    });
+    group.finish();
+}
+
+fn bench(c: &mut Criterion) {
+    bench_size::<8>(c);
+    bench_size::<32>(c);
+    bench_size::<256>(c);
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -1,11 +1,8 @@
-use std::pin::pin;
-
-use bytes::BytesMut;
-use rateless_iblt::{Decoder, Encoder, Symbol};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use futures::{SinkExt, StreamExt};
+use rateless_iblt::{Decoder, Encoder, SymbolCodec};
+use tokio::io::AsyncRead;
+use tokio_util::codec::{FramedRead, FramedWrite};
 use zerocopy::little_endian;
-use zerocopy::IntoBytes;
-use zerocopy_buf::ZeroCopyBuf;
 
 #[tokio::main]
 async fn main() {
@@ -18,11 +15,11 @@ async fn main() {
     let mut decoder = Decoder::default();
 
     // bob connects to alice
-    let mut alice = decode_stream(connect_to_alice());
+    let mut alice = FramedRead::new(connect_to_alice(), SymbolCodec::new());
 
     // bob drives the decoder
     while !decoder.is_complete() {
-        let alice_symbol = alice.recv().await.unwrap();
+        let alice_symbol = alice.next().await.unwrap().unwrap();
         let bob_symbol = bob.next().unwrap();
 
         decoder.push(alice_symbol, bob_symbol);
@@ -35,44 +32,18 @@ async fn main() {
     assert_eq!(bob_new, vec![5], "bob has 5 but alice does not");
 }
 
-fn decode_stream(
-    s: impl AsyncRead + Send + 'static,
-) -> tokio::sync::mpsc::Receiver<Symbol<little_endian::I64>> {
-    let (tx, rx) = tokio::sync::mpsc::channel(1);
-
-    tokio::spawn(async move {
-        let mut s = pin!(s);
-        let mut buf = BytesMut::new();
-
-        loop {
-            // try pull entries from the buf
-            while let Ok(entry) = buf.try_get() {
-                if tx.send(*entry).await.is_err() {
-                    break;
-                }
-            }
-
-            // read more data into the buf
-            if s.as_mut().read_buf(&mut buf).await.is_err() {
-                break;
-            }
-        }
-    });
-
-    rx
-}
-
 fn connect_to_alice() -> impl AsyncRead + Send + 'static {
-    let (rx, mut tx) = tokio::io::duplex(64);
+    let (rx, tx) = tokio::io::duplex(64);
 
     tokio::spawn(async move {
         // alice constructs her encoder
         let mut alice = Encoder::<little_endian::I64>::default();
         alice.extend([1, 2, 3, 4].map(little_endian::I64::new));
 
+        let mut tx = FramedWrite::new(tx, SymbolCodec::new());
         for entry in alice {
             // alice streams out her entries to bob
-            if tx.write_all(entry.as_bytes()).await.is_err() {
+            if tx.send(entry).await.is_err() {
                 break;
             }
         }
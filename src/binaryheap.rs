@@ -1,8 +1,20 @@
 //! Taken with modifications from std::collections::BinaryHeap.
+//!
+//! The `_by` variants call `moved(element, pos)` for every element that ends up at a
+//! new position, so that callers can keep track of where each element is.
 
 pub(crate) fn sift_up<T: Ord>(v: &mut [T], start: usize, pos: usize) -> usize {
+    sift_up_by(v, start, pos, |_, _| {})
+}
+
+pub(crate) fn sift_up_by<T: Ord>(
+    v: &mut [T],
+    start: usize,
+    pos: usize,
+    moved: impl FnMut(&T, usize),
+) -> usize {
     // Take out the value at `pos` and create a hole.
-    let mut hole = Hole::new(v, pos);
+    let mut hole = Hole::new(v, pos, moved);
 
     while hole.pos > start {
         let parent = (hole.pos - 1) / 2;
@@ -14,12 +26,16 @@ pub(crate) fn sift_up<T: Ord>(v: &mut [T], start: usize, pos: usize) -> usize {
         hole.move_to(parent)
     }
 
-    hole.pos
+    hole.finish()
 }
 
 pub(crate) fn sift_down<T: Ord>(v: &mut [T], pos: usize) {
+    sift_down_by(v, pos, |_, _| {})
+}
+
+pub(crate) fn sift_down_by<T: Ord>(v: &mut [T], pos: usize, moved: impl FnMut(&T, usize)) {
     let end = v.len();
-    let mut hole = Hole::new(v, pos);
+    let mut hole = Hole::new(v, pos, moved);
     let mut child = 2 * hole.pos + 1;
 
     // Loop invariant: child == 2 * hole.pos() + 1.
@@ -29,6 +45,7 @@ pub(crate) fn sift_down<T: Ord>(v: &mut [T], pos: usize) {
 
         // if we are already in order, stop.
         if *hole.element() >= hole.data[child] {
+            hole.finish();
             return;
         }
 
@@ -39,30 +56,32 @@ pub(crate) fn sift_down<T: Ord>(v: &mut [T], pos: usize) {
     if child + 1 == end && *hole.element() < hole.data[child] {
         hole.move_to(child);
     }
+    hole.finish();
 }
 
-pub(crate) fn rebuild<T: Ord>(v: &mut [T]) {
+pub(crate) fn rebuild_by<T: Ord>(v: &mut [T], mut moved: impl FnMut(&T, usize)) {
     let mut n = v.len() / 2;
     while n > 0 {
         n -= 1;
-        sift_down(v, n);
+        sift_down_by(v, n, &mut moved);
     }
 }
 
 /// Hole represents a hole in a slice i.e., an index without valid value
 /// (because it was moved from or duplicated).
-struct Hole<'a, T: 'a> {
+struct Hole<'a, T: 'a, F: FnMut(&T, usize)> {
     data: &'a mut [T],
     pos: usize,
+    moved: F,
 }
 
-impl<'a, T> Hole<'a, T> {
+impl<'a, T, F: FnMut(&T, usize)> Hole<'a, T, F> {
     /// Creates a new `Hole` at index `pos`.
     ///
     /// Unsafe because pos must be within the data slice.
     #[inline]
-    fn new(data: &'a mut [T], pos: usize) -> Self {
-        Hole { data, pos }
+    fn new(data: &'a mut [T], pos: usize, moved: F) -> Self {
+        Hole { data, pos, moved }
     }
 
     /// Returns a reference to the element removed.
@@ -75,6 +94,16 @@ impl<'a, T> Hole<'a, T> {
     #[inline]
     fn move_to(&mut self, index: usize) {
         self.data.swap(index, self.pos);
+        (self.moved)(&self.data[self.pos], self.pos);
         self.pos = index;
     }
+
+    /// Reports the final position of the element, if any, and returns it.
+    #[inline]
+    fn finish(mut self) -> usize {
+        if let Some(element) = self.data.get(self.pos) {
+            (self.moved)(element, self.pos);
+        }
+        self.pos
+    }
 }
@@ -0,0 +1,167 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
+use once_cell::race::OnceBox;
+
+use crate::{Blake3, EncoderIter, Item, Symbol, SymbolHasher};
+
+/// A shareable cache of the coded symbols produced by an [`EncoderIter`].
+///
+/// The first `k` symbols are computed once up front. Many peers can then read the
+/// cache concurrently without locking. If a peer needs more symbols than are cached,
+/// the cache is extended lazily and the new symbols are shared with all other readers.
+///
+/// The symbols produced are identical to those of a fresh [`EncoderIter`].
+pub struct SymbolCache<T, H = Blake3, const W: usize = 16> {
+    inner: Arc<Inner<T, H, W>>,
+}
+
+impl<T, H, const W: usize> Clone for SymbolCache<T, H, W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// Yields to other threads while waiting for the extension encoder with `std`.
+#[cfg(feature = "std")]
+type Lock<T> = spin::mutex::SpinMutex<T, spin::relax::Yield>;
+#[cfg(not(feature = "std"))]
+type Lock<T> = spin::mutex::SpinMutex<T>;
+
+/// Chunks double in size, so this is enough for any number of symbols.
+const MAX_CHUNKS: usize = 64;
+
+struct Inner<T, H, const W: usize> {
+    /// Positioned at the end of the last chunk. Only locked to extend the cache.
+    encoder: Lock<EncoderIter<T, H, W>>,
+    chunks: [OnceBox<Chunk<T, W>>; MAX_CHUNKS],
+}
+
+struct Chunk<T, const W: usize> {
+    start: u64,
+    symbols: Vec<Symbol<T, W>>,
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> SymbolCache<T, H, W> {
+    /// Caches the first `k` symbols of `encoder` (at least one).
+    pub fn new(mut encoder: EncoderIter<T, H, W>, k: usize) -> Self {
+        encoder.seek(0);
+        let symbols = encoder.by_ref().take(k.max(1)).collect();
+
+        let chunks = core::array::from_fn(|_| OnceBox::new());
+        let _ = chunks[0].set(Box::new(Chunk { start: 0, symbols }));
+        Self {
+            inner: Arc::new(Inner {
+                encoder: Lock::new(encoder),
+                chunks,
+            }),
+        }
+    }
+
+    /// The number of symbols that are currently cached.
+    pub fn cached_len(&self) -> usize {
+        self.inner
+            .chunks
+            .iter()
+            .map_while(OnceBox::get)
+            .map(|chunk| chunk.symbols.len())
+            .sum()
+    }
+
+    pub(crate) fn hasher(&self) -> H {
+        self.inner.encoder.lock().hasher.clone()
+    }
+
+    /// Returns an iterator over the cached symbols, extending the cache as needed.
+    pub fn iter(&self) -> SymbolCacheIter<T, H, W> {
+        SymbolCacheIter {
+            inner: self.inner.clone(),
+            chunk: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Inner<T, H, W> {
+    /// Returns chunk `i`, computing it if this is the first reader to need it.
+    fn chunk(&self, i: usize) -> Option<&Chunk<T, W>> {
+        let slot = self.chunks.get(i)?;
+        if let Some(chunk) = slot.get() {
+            return Some(chunk);
+        }
+
+        let mut encoder = self.encoder.lock();
+        // another reader may have computed it while this one waited for the lock.
+        if let Some(chunk) = slot.get() {
+            return Some(chunk);
+        }
+        let prev = self.chunks[i - 1].get()?;
+        let start = prev.start + prev.symbols.len() as u64;
+        // the chunks double in size to amortise extending the cache.
+        let symbols = encoder.by_ref().take(start as usize).collect();
+        let _ = slot.set(Box::new(Chunk { start, symbols }));
+        slot.get()
+    }
+}
+
+/// An iterator over the symbols in a [`SymbolCache`].
+pub struct SymbolCacheIter<T, H = Blake3, const W: usize = 16> {
+    inner: Arc<Inner<T, H, W>>,
+    chunk: usize,
+    offset: usize,
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Iterator for SymbolCacheIter<T, H, W> {
+    type Item = Symbol<T, W>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = self.inner.chunk(self.chunk)?;
+        if self.offset == chunk.symbols.len() {
+            self.chunk += 1;
+            self.offset = 0;
+            chunk = self.inner.chunk(self.chunk)?;
+        }
+
+        let symbol = chunk.symbols[self.offset].copy();
+        self.offset += 1;
+        Some(symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use crate::{Encoder, SymbolCache};
+
+    #[test]
+    fn matches_encoder() {
+        let entries: Vec<u64> = (0..1000).collect();
+        let expected: Vec<_> = Encoder::from_iter(entries.iter().copied())
+            .into_iter()
+            .take(500)
+            .collect();
+
+        let cache = SymbolCache::new(Encoder::from_iter(entries).into_iter(), 10);
+        assert_eq!(cache.cached_len(), 10);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || cache.iter().take(500).collect::<Vec<_>>())
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), expected);
+        }
+
+        // 10 + 10 + 20 + 40 + 80 + 160 + 320
+        assert_eq!(cache.cached_len(), 640);
+
+        // later readers only read the cached chunks.
+        assert_eq!(cache.iter().take(640).count(), 640);
+        assert_eq!(cache.cached_len(), 640);
+    }
+}
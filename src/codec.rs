@@ -0,0 +1,155 @@
+use core::marker::PhantomData;
+use std::io;
+
+use bytes::{Buf, BytesMut};
+use zerocopy::{little_endian, FromBytes, FromZeros, Immutable, IntoBytes};
+
+use crate::{
+    wire::{decode_symbol, encode_symbol, Header, WireError},
+    Symbol,
+};
+
+/// A [`tokio_util::codec`] for framing streams of [`Symbol`]s.
+///
+/// Symbols are framed using the [wire format](crate::wire). The stream header is
+/// not part of the codec, and can be exchanged before framing the stream.
+pub struct SymbolCodec<T, const W: usize = 16> {
+    header: Header,
+    _item: PhantomData<T>,
+}
+
+impl<T, const W: usize> SymbolCodec<T, W> {
+    /// A codec for symbols with full checksums and fixed width counts.
+    ///
+    /// This matches the in-memory layout of [`Symbol`] for unaligned items.
+    pub fn new() -> Self {
+        Self {
+            header: Header {
+                flags: 0,
+                checksum_width: W as u8,
+                item_width: little_endian::U32::new(core::mem::size_of::<T>() as u32),
+                ..Header::new_zeroed()
+            },
+            _item: PhantomData,
+        }
+    }
+
+    /// A codec for symbols in the format described by `header`.
+    ///
+    /// Fails if the flags, checksum width or item width of `header` are not supported
+    /// for `Symbol<T, W>`. The magic, version and hash are not checked.
+    pub fn from_header(header: &Header) -> Result<Self, WireError> {
+        header.check_format::<T, W>()?;
+        Ok(Self {
+            header: *header,
+            _item: PhantomData,
+        })
+    }
+}
+
+impl<T, const W: usize> Default for SymbolCodec<T, W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const W: usize> Clone for SymbolCodec<T, W> {
+    fn clone(&self) -> Self {
+        Self {
+            header: self.header,
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T: FromBytes + IntoBytes + Immutable, const W: usize> tokio_util::codec::Decoder
+    for SymbolCodec<T, W>
+{
+    type Item = Symbol<T, W>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match decode_symbol(&self.header, src)? {
+            Some((symbol, len)) => {
+                src.advance(len);
+                Ok(Some(symbol))
+            }
+            None => {
+                // the fixed part of the next symbol is known, so reserve space for it.
+                let len = core::mem::size_of::<T>() + self.header.width() + 1;
+                src.reserve(len.saturating_sub(src.len()));
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<T: IntoBytes + Immutable, const W: usize> tokio_util::codec::Encoder<Symbol<T, W>>
+    for SymbolCodec<T, W>
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Symbol<T, W>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        encode_symbol(&self.header, &item, |b| {
+            dst.extend_from_slice(b);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::SymbolCodec;
+    use crate::{wire::Header, ChecksumWidth, Encoder as SetEncoder};
+
+    #[test]
+    fn partial_frames() {
+        let remote = SetEncoder::from_iter(0..100u64).into_iter();
+        let header = Header::compact(&remote, ChecksumWidth::Bytes8).unwrap();
+        let mut symbols: Vec<_> = remote.take(50).collect();
+
+        let mut codec = SymbolCodec::<u64>::from_header(&header).unwrap();
+        let mut buf = BytesMut::new();
+        for symbol in &symbols {
+            codec.encode(*symbol, &mut buf).unwrap();
+        }
+
+        // feed the bytes one at a time.
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for b in buf {
+            src.extend_from_slice(&[b]);
+            decoded.extend(codec.decode(&mut src).unwrap());
+        }
+        assert!(src.is_empty());
+
+        // only the first 8 checksum bytes are sent.
+        for symbol in &mut symbols {
+            symbol.checksum[8..].fill(0);
+        }
+        assert_eq!(decoded, symbols);
+    }
+
+    #[test]
+    fn reject_unsupported_headers() {
+        let remote = SetEncoder::from_iter(0..100u64).into_iter();
+        let header = Header::new(&remote);
+        assert!(SymbolCodec::<u64>::from_header(&header).is_ok());
+        assert!(SymbolCodec::<u32>::from_header(&header).is_err());
+        assert!(SymbolCodec::<u64, 8>::from_header(&header).is_err());
+
+        for (checksum_width, flags) in [(200, 0), (0, 0), (12, 0), (16, 0x80)] {
+            let header = Header {
+                checksum_width,
+                flags,
+                ..header
+            };
+            assert!(SymbolCodec::<u64>::from_header(&header).is_err());
+        }
+    }
+}
@@ -1,95 +1,416 @@
-use alloc::vec::Vec;
-use zerocopy::{FromBytes, Immutable, IntoBytes};
+use core::fmt;
 
-use crate::{binaryheap, index::IndexGenerator, EncoderIter, Symbol};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
 
-pub fn set_difference<T: FromBytes + IntoBytes + Immutable>(
+use crate::{
+    binaryheap, index::IndexGenerator, Blake3, ChecksumWidth, Encoder, EncoderIter, IndexedSymbol,
+    Item, Key, Symbol, SymbolHasher,
+};
+
+/// An impossible state was found while decoding.
+///
+/// This is caused by a corrupted stream or by a peer that is not following the protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The first symbol reported a negative set size.
+    InvalidSetSize,
+    /// The count of the cell at `index` is larger than the set sizes allow.
+    CountOutOfRange { index: u64 },
+    /// The pure cell at `index` holds an entry that was already decoded.
+    DuplicateEntry { index: u64 },
+    /// The pure cell at `index` holds an entry that does not map to `index`.
+    IndexMismatch { index: u64 },
+    /// The remote peer is using a different key.
+    KeyMismatch,
+    /// The symbol at `index` of a full transfer does not hold a single entry.
+    InvalidEntry { index: u64 },
+    /// The indexed symbol at `index` is too far past the symbols received so far.
+    IndexTooFar { index: u64 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidSetSize => f.write_str("invalid set size"),
+            DecodeError::CountOutOfRange { index } => {
+                write!(f, "count out of range in cell {index}")
+            }
+            DecodeError::DuplicateEntry { index } => {
+                write!(f, "duplicate entry in cell {index}")
+            }
+            DecodeError::IndexMismatch { index } => {
+                write!(f, "entry does not map to cell {index}")
+            }
+            DecodeError::KeyMismatch => f.write_str("key mismatch"),
+            DecodeError::InvalidEntry { index } => {
+                write!(f, "symbol {index} of full transfer is not an entry")
+            }
+            DecodeError::IndexTooFar { index } => {
+                write!(f, "indexed symbol {index} is too far ahead")
+            }
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// The default for [`Decoder::set_max_gap`].
+const MAX_GAP: u64 = 4096;
+
+/// Decodes the difference between the `remote` and `local` symbol streams.
+///
+/// Returns `None` if either stream ends before decoding is complete,
+/// or if the streams are found to be corrupted.
+pub fn set_difference<T: Item>(
     remote: impl IntoIterator<Item = Symbol<T>>,
     local: impl IntoIterator<Item = Symbol<T>>,
 ) -> Option<(Vec<T>, Vec<T>)> {
+    decode_with(remote, local, Decoder::consume)
+}
+
+/// Decodes the `remote` and `local` symbol streams, and passes the complete
+/// decoder to `consume`.
+///
+/// Returns `None` if either stream ends before decoding is complete,
+/// or if the streams are found to be corrupted.
+pub(crate) fn decode_with<T: Item, R>(
+    remote: impl IntoIterator<Item = Symbol<T>>,
+    local: impl IntoIterator<Item = Symbol<T>>,
+    consume: impl FnOnce(Decoder<T>) -> R,
+) -> Option<R> {
     let mut decoder = Decoder::default();
 
     let mut a = remote.into_iter();
     let mut b = local.into_iter();
 
     loop {
-        decoder.push(a.next()?, b.next()?);
+        decoder.try_push(a.next()?, b.next()?).ok()?;
         if decoder.is_complete() {
-            return Some(decoder.consume());
+            return Some(consume(decoder));
         }
     }
 }
 
-pub struct Decoder<T> {
-    remote: EncoderIter<T>,
-    local: EncoderIter<T>,
-    symbols: Vec<Symbol<T>>,
+pub struct Decoder<T, H = Blake3, const W: usize = 16> {
+    remote: EncoderIter<T, H, W>,
+    local: EncoderIter<T, H, W>,
+    symbols: Vec<Symbol<T, W>>,
+    /// Whether the remote symbol of each cell was received. Only indexed symbols can be missing.
+    known: Vec<bool>,
     pure_heap: Vec<usize>,
-    remote_count: u64,
-    local_count: u64,
+    remote_count: i64,
+    local_count: i64,
+    pub(crate) hasher: H,
+    checksum_width: ChecksumWidth,
+    /// The local symbols, when the decoder was created with [`Decoder::with_local`].
+    local_symbols: Option<EncoderIter<T, H, W>>,
+    /// Set when decoding a signed difference stream, whose set size can be negative.
+    signed: bool,
+    /// How far past the received cells an indexed symbol can be.
+    max_gap: u64,
+    /// The checksums of remote entries that were guessed rather than decoded.
+    guesses: BTreeSet<[u8; W]>,
+    /// The number of decoded or guessed entries with each checksum, on either side.
+    decoded: BTreeMap<[u8; W], usize>,
 }
 
 impl<T> Default for Decoder<T> {
     fn default() -> Self {
+        Self::with_hasher(Blake3::default())
+    }
+}
+
+impl<T> Decoder<T> {
+    /// Creates a decoder for symbols encoded with [`Encoder::with_key`](crate::Encoder::with_key).
+    pub fn with_key(key: Key) -> Self {
+        Self::with_hasher(Blake3::with_key(key))
+    }
+}
+
+impl<T, H: SymbolHasher, const W: usize> Decoder<T, H, W> {
+    /// Creates a decoder for symbols encoded with
+    /// [`Encoder::with_hasher`](crate::Encoder::with_hasher) using the same hasher.
+    pub fn with_hasher(hasher: H) -> Self {
         Self {
-            remote: Default::default(),
-            local: Default::default(),
+            remote: EncoderIter::empty(hasher.clone()),
+            local: EncoderIter::empty(hasher.clone()),
             symbols: Default::default(),
+            known: Vec::new(),
             pure_heap: Vec::new(),
             remote_count: 0,
             local_count: 0,
+            hasher,
+            checksum_width: Symbol::<T, W>::WIDTH,
+            local_symbols: None,
+            signed: false,
+            max_gap: MAX_GAP,
+            guesses: BTreeSet::new(),
+            decoded: BTreeMap::new(),
         }
     }
+
+    /// Sets the number of checksum bytes that are checked for each cell.
+    ///
+    /// This must be no larger than the checksum width of the remote symbols.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is wider than the checksums of this decoder's symbols.
+    pub fn set_checksum_width(&mut self, width: ChecksumWidth) {
+        assert!(width.bytes() <= W, "checksum width is wider than {W} bytes");
+        self.checksum_width = width;
+    }
+
+    /// The number of checksum bytes that are checked for each cell.
+    pub fn checksum_width(&self) -> ChecksumWidth {
+        self.checksum_width
+    }
+
+    /// Sets how many symbols past the ones received so far an indexed symbol can be.
+    ///
+    /// [`Decoder::push_indexed`] allocates all cells up to the index of each symbol,
+    /// so this bounds the memory a forged index can use. It should be larger than the
+    /// number of symbols the transport can lose or reorder in a row. The default is 4096.
+    pub fn set_max_gap(&mut self, gap: u64) {
+        self.max_gap = gap;
+    }
+
+    /// Identifies the key used by this decoder, without revealing it.
+    pub fn key_id(&self) -> [u8; 16] {
+        self.hasher.key_id()
+    }
+
+    /// Checks that the remote peer's [`EncoderIter::key_id`] matches the key used by this decoder.
+    pub fn check_key_id(&self, remote: [u8; 16]) -> Result<(), DecodeError> {
+        if remote != self.key_id() {
+            return Err(DecodeError::KeyMismatch);
+        }
+        Ok(())
+    }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> Decoder<T> {
+impl<T: Item, H: SymbolHasher, const W: usize> Decoder<T, H, W> {
     pub fn is_complete(&self) -> bool {
-        !self.symbols.is_empty() && self.symbols[0].is_empty_cell()
+        self.known.first() == Some(&true) && self.symbols[0].is_empty_cell(self.checksum_width)
     }
 
     pub fn consume(self) -> (Vec<T>, Vec<T>) {
         (self.remote.entries, self.local.entries)
     }
 
-    pub fn push(&mut self, mut remote: Symbol<T>, mut local: Symbol<T>) {
+    /// The remote and local entries decoded so far.
+    pub(crate) fn entries(&self) -> (&[T], &[T]) {
+        (&self.remote.entries, &self.local.entries)
+    }
+
+    /// Creates a decoder that produces the local symbols itself, from the `local` set.
+    ///
+    /// Remote symbols are then pushed with [`Decoder::push_remote`].
+    pub fn with_local(local: Encoder<T, H, W>) -> Self {
+        let local = local.into_iter();
+        let hasher = local.hasher.clone();
+        Self {
+            local_symbols: Some(local),
+            ..Self::with_hasher(hasher)
+        }
+    }
+
+    /// Pushes the next remote symbol into the decoder, along with the matching
+    /// local symbol.
+    ///
+    /// After an error is returned, the decoder should be discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder was not created with [`Decoder::with_local`].
+    pub fn push_remote(&mut self, remote: Symbol<T, W>) -> Result<(), DecodeError> {
+        let local = self
+            .local_symbols
+            .as_mut()
+            .expect("decoder was not created with a local set")
+            .must_next();
+        self.try_push(remote, local)
+    }
+
+    /// Pushes the next symbol of a difference stream made by [`subtract`](crate::subtract).
+    ///
+    /// The difference is decoded on its own. Entries only in the first set are
+    /// returned as remote entries by [`Decoder::consume`], and entries only in
+    /// the second set as local entries.
+    pub fn push_difference(&mut self, difference: Symbol<T, W>) -> Result<(), DecodeError> {
+        self.signed = true;
+        self.try_push(difference, Symbol::default())
+    }
+
+    /// Pushes the next remote and local symbols into the decoder.
+    ///
+    /// Errors are ignored. Use [`Decoder::try_push`] to detect a corrupted stream.
+    pub fn push(&mut self, remote: Symbol<T, W>, local: Symbol<T, W>) {
+        let _ = self.try_push(remote, local);
+    }
+
+    /// Pushes the next remote and local symbols into the decoder, reporting
+    /// impossible states caused by a corrupted stream or a mismatched peer.
+    ///
+    /// After an error is returned, the decoder should be discarded.
+    pub fn try_push(
+        &mut self,
+        mut remote: Symbol<T, W>,
+        mut local: Symbol<T, W>,
+    ) -> Result<(), DecodeError> {
         if self.symbols.is_empty() {
-            self.remote_count = remote.count.get() as u64;
-            self.local_count = local.count.get() as u64;
+            if !self.signed && (remote.count.get() < 0 || local.count.get() < 0) {
+                return Err(DecodeError::InvalidSetSize);
+            }
+            self.remote_count = remote.count.get();
+            self.local_count = local.count.get();
         } else {
-            remote.decode_count(self.symbols.len(), self.remote_count);
-            local.decode_count(self.symbols.len(), self.local_count);
+            let i = self.symbols.len() as u64;
+            remote.decode_count(i, self.remote_count);
+            local.decode_count(i, self.local_count);
         }
 
         let cell = remote - local - self.remote.must_next() + self.local.must_next();
 
-        if cell.is_pure_cell() {
+        let count = i128::from(cell.count.get());
+        if !self.signed
+            && (count > i128::from(self.remote_count)
+                || count < -i128::from(self.local_count) - self.guesses.len() as i128)
+        {
+            return Err(DecodeError::CountOutOfRange {
+                index: self.symbols.len() as u64,
+            });
+        }
+
+        if cell.is_pure_cell(&self.hasher, self.checksum_width) {
             self.pure_heap.push(self.symbols.len());
         }
         self.symbols.push(cell);
+        self.known.push(true);
+
+        self.peel_pure_cells()
+    }
+
+    /// Pushes a remote symbol that may arrive out of order, or not at all.
+    ///
+    /// Cells whose symbols have not arrived are treated as unknown, so decoding
+    /// completes once enough symbols have arrived, whichever they are. Symbols that
+    /// arrive more than once are ignored.
+    ///
+    /// Symbols more than [`Decoder::set_max_gap`] past the cells received so far are
+    /// rejected. After an error is returned, the decoder should be discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder was not created with [`Decoder::with_local`].
+    pub fn push_indexed(&mut self, remote: IndexedSymbol<T, W>) -> Result<(), DecodeError> {
+        let i = remote.index.get();
+        if i > (self.symbols.len() as u64).saturating_add(self.max_gap) {
+            return Err(DecodeError::IndexTooFar { index: i });
+        }
+        let local = self
+            .local_symbols
+            .as_mut()
+            .expect("decoder was not created with a local set");
+        let local_count = local.entries.len() as i64;
+
+        // cells start out holding everything but the remote symbol.
+        while self.symbols.len() as u64 <= i {
+            let j = self.symbols.len() as u64;
+            let mut l = local.must_next();
+            if j > 0 {
+                l.decode_count(j, local_count);
+            }
+            let cell =
+                Symbol::<T, W>::default() - l - self.remote.must_next() + self.local.must_next();
+            self.symbols.push(cell);
+            self.known.push(false);
+        }
+
+        let i = i as usize;
+        if self.known[i] {
+            return Ok(());
+        }
+        self.known[i] = true;
+        self.symbols[i] += remote.symbol;
+
+        if self.symbols[i].is_pure_cell(&self.hasher, self.checksum_width) {
+            let old_index = self.pure_heap.len();
+            self.pure_heap.push(i);
+            binaryheap::sift_up(&mut self.pure_heap, 0, old_index);
+        }
 
+        self.peel_pure_cells()
+    }
+
+    /// Speculatively peels `value` as a remote entry, before it is found in a pure cell.
+    ///
+    /// This saves symbols when the entry is likely to be in the remote set, such as when
+    /// it was already decoded from another peer. A wrong guess shows up later as a pure
+    /// cell that removes the entry, which undoes the guess.
+    pub(crate) fn guess_remote(&mut self, value: &T) -> Result<(), DecodeError> {
+        let checksum = self.hasher.hash(value.bytes());
+        if self.is_decoded(value, &checksum) {
+            return Ok(());
+        }
+
+        let mut symbol = Symbol::<T, W>::default();
+        symbol.add_entry(value, &checksum);
+        let index = self.peel(&symbol);
+        self.remote.push_unchecked(value.copy(), checksum, index);
+        *self.decoded.entry(checksum).or_default() += 1;
+        self.guesses.insert(checksum);
+
+        self.peel_pure_cells()
+    }
+
+    fn peel_pure_cells(&mut self) -> Result<(), DecodeError> {
         while !self.pure_heap.is_empty() {
             let i = self.pure_heap.swap_remove(0);
             binaryheap::sift_down(&mut self.pure_heap, 0);
 
-            let symbol = self.symbols[i].copy();
-            if !symbol.is_pure_cell() {
+            let mut symbol = self.symbols[i].copy();
+            if !symbol.is_pure_cell(&self.hasher, self.checksum_width) {
                 continue;
             }
 
-            // peel off this cell in all indices
-            let mut index = IndexGenerator::new(symbol.checksum);
-            while let Some(s) = index_mut_u64(&mut self.symbols, index.current()) {
-                *s -= &symbol;
+            // only part of the checksum might have been sent, so recompute the rest.
+            symbol.checksum = self.hasher.hash(symbol.sum.bytes());
 
-                if s.is_pure_cell() {
-                    let old_index = self.pure_heap.len();
-                    self.pure_heap.push(index.current() as usize);
-                    binaryheap::sift_up(&mut self.pure_heap, 0, old_index);
-                }
+            let guessed = if symbol.count == -1 && self.guesses.contains(&symbol.checksum) {
+                self.remote.find_entry(&symbol.sum, &symbol.checksum)
+            } else {
+                None
+            };
+            if guessed.is_none() && self.is_decoded(&symbol.sum, &symbol.checksum) {
+                return Err(DecodeError::DuplicateEntry { index: i as u64 });
+            }
+
+            let mut expected = IndexGenerator::new(&symbol.checksum);
+            expected.skip_to(i as u64);
+            if expected.current() != i as u64 {
+                return Err(DecodeError::IndexMismatch { index: i as u64 });
+            }
 
-                index.next();
+            let index = self.peel(&symbol);
+
+            if let Some(guessed) = guessed {
+                self.guesses.remove(&symbol.checksum);
+                self.remote
+                    .remove_at(guessed, &symbol.sum, &symbol.checksum);
+                if let Some(n) = self.decoded.get_mut(&symbol.checksum) {
+                    *n -= 1;
+                    if *n == 0 {
+                        self.decoded.remove(&symbol.checksum);
+                    }
+                }
+                continue;
             }
 
+            *self.decoded.entry(symbol.checksum).or_default() += 1;
             if symbol.count == 1 {
                 self.remote
                     .push_unchecked(symbol.sum, symbol.checksum, index);
@@ -98,9 +419,156 @@ impl<T: FromBytes + IntoBytes + Immutable> Decoder<T> {
                     .push_unchecked(symbol.sum, symbol.checksum, index);
             }
         }
+
+        Ok(())
+    }
+
+    /// Whether `value` was already decoded or guessed, on either side.
+    fn is_decoded(&self, value: &T, checksum: &[u8; W]) -> bool {
+        // checksums can collide when they are narrow, so confirm the rare hits
+        // against the entries themselves.
+        self.decoded.contains_key(checksum)
+            && (self.remote.find_entry(value, checksum).is_some()
+                || self.local.find_entry(value, checksum).is_some())
+    }
+
+    /// Peels `symbol` off all the cells its entry maps to, returning the index
+    /// generator positioned at the next cell.
+    fn peel(&mut self, symbol: &Symbol<T, W>) -> IndexGenerator {
+        let mut index = IndexGenerator::new(&symbol.checksum);
+        while let Some(s) = index_mut_u64(&mut self.symbols, index.current()) {
+            *s -= symbol;
+
+            let known = self.known[index.current() as usize];
+            if known && s.is_pure_cell(&self.hasher, self.checksum_width) {
+                let old_index = self.pure_heap.len();
+                self.pure_heap.push(index.current() as usize);
+                binaryheap::sift_up(&mut self.pure_heap, 0, old_index);
+            }
+
+            index.next();
+        }
+        index
     }
 }
 
 fn index_mut_u64<T>(s: &mut [T], i: u64) -> Option<&mut T> {
     s.get_mut(usize::try_from(i).ok()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use zerocopy::IntoBytes;
+
+    use crate::{
+        index::IndexGenerator, Blake3, DecodeError, Decoder, Encoder, Symbol, SymbolHasher,
+    };
+
+    fn symbols(entries: &[u64], n: usize) -> Vec<Symbol<u64>> {
+        Encoder::from_iter(entries.iter().copied())
+            .into_iter()
+            .take(n)
+            .collect()
+    }
+
+    /// Adds `value` into the symbol at `i > 0`, as if it were an extra remote entry.
+    fn corrupt(symbol: &mut Symbol<u64>, value: u64) {
+        let mut s = Symbol::default();
+        s.add_entry(&value, &Blake3::default().hash(value.as_bytes()));
+        s.count.set(-1);
+        *symbol += s;
+    }
+
+    fn decode(remote: Vec<Symbol<u64>>, local: Vec<Symbol<u64>>) -> Result<(), DecodeError> {
+        let mut decoder = Decoder::default();
+        for (remote, local) in core::iter::zip(remote, local) {
+            decoder.try_push(remote, local)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn with_local() {
+        let mut decoder = Decoder::with_local(Encoder::from_iter([1u64, 2, 3, 5]));
+        for remote in Encoder::from_iter([1u64, 2, 3, 4]) {
+            decoder.push_remote(remote).unwrap();
+            if decoder.is_complete() {
+                break;
+            }
+        }
+
+        assert_eq!(decoder.consume(), (vec![4], vec![5]));
+    }
+
+    fn difference<const W: usize>() -> (Vec<u64>, Vec<u64>) {
+        crate::tests::difference::<_, W>(Blake3::default(), 0..1000, 10..1010)
+    }
+
+    #[test]
+    fn checksum_widths() {
+        assert_eq!(core::mem::size_of::<Symbol<[u8; 8], 4>>(), 20);
+
+        let expected = (Vec::from_iter(0..10), Vec::from_iter(1000..1010));
+        assert_eq!(difference::<4>(), expected);
+        assert_eq!(difference::<8>(), expected);
+        assert_eq!(difference::<16>(), expected);
+        assert_eq!(difference::<32>(), expected);
+    }
+
+    #[test]
+    fn invalid_set_size() {
+        let mut remote = symbols(&[1, 2, 3], 1);
+        remote[0].count.set(-1);
+        let local = symbols(&[1, 2, 3], 1);
+
+        assert_eq!(decode(remote, local), Err(DecodeError::InvalidSetSize));
+    }
+
+    #[test]
+    fn count_out_of_range() {
+        let mut remote = symbols(&[1, 2, 3], 10);
+        remote[5].count.set(-100);
+        let local = symbols(&[1, 2, 3], 10);
+
+        assert_eq!(
+            decode(remote, local),
+            Err(DecodeError::CountOutOfRange { index: 5 })
+        );
+    }
+
+    #[test]
+    fn index_mismatch() {
+        let mut gen = IndexGenerator::new(&Blake3::default().hash::<16>(42u64.as_bytes()));
+        gen.next();
+        let i = (1..).find(|&i| i != gen.current()).unwrap() as usize;
+
+        let mut remote = symbols(&[1, 2, 3], 10);
+        corrupt(&mut remote[i], 42);
+        let local = symbols(&[1, 2, 3], 10);
+
+        assert_eq!(
+            decode(remote, local),
+            Err(DecodeError::IndexMismatch { index: i as u64 })
+        );
+    }
+
+    #[test]
+    fn duplicate_entry() {
+        // only insert 42 into one of its cells. Once it is peeled,
+        // the other cells it maps to will contain it again.
+        let mut gen = IndexGenerator::new(&Blake3::default().hash::<16>(42u64.as_bytes()));
+        gen.next();
+        let i = gen.current() as usize;
+
+        let mut remote = symbols(&[1, 2, 3], i + 1);
+        corrupt(&mut remote[i], 42);
+        let local = symbols(&[1, 2, 3], i + 1);
+
+        assert!(matches!(
+            decode(remote, local),
+            Err(DecodeError::DuplicateEntry { .. })
+        ));
+    }
+}
@@ -1,124 +1,214 @@
-use alloc::vec::Vec;
+use alloc::{collections::BTreeSet, vec::Vec};
 
-use zerocopy::{FromBytes, Immutable, IntoBytes};
-
-use crate::{binaryheap, hash, index::IndexGenerator, Symbol};
+use crate::{
+    binaryheap,
+    index::{expected_count, IndexGenerator},
+    xor_mut, Blake3, Item, Key, Symbol, SymbolHasher,
+};
 
 #[derive(Clone)]
-pub struct Encoder<T> {
-    entries: Vec<T>,
+pub struct Encoder<T, H = Blake3, const W: usize = 16> {
+    pub(crate) entries: Vec<T>,
+    pub(crate) hasher: H,
 }
 
 impl<T> Default for Encoder<T> {
     fn default() -> Self {
+        Self::with_hasher(Blake3::default())
+    }
+}
+
+impl<T> Encoder<T> {
+    /// Creates an encoder that uses BLAKE3 keyed hashing.
+    ///
+    /// The remote peer must decode with [`Decoder::with_key`](crate::Decoder::with_key) using the same key.
+    pub fn with_key(key: Key) -> Self {
+        Self::with_hasher(Blake3::with_key(key))
+    }
+}
+
+impl<T, H: SymbolHasher, const W: usize> Encoder<T, H, W> {
+    /// Creates an encoder that uses `hasher` for checksums.
+    ///
+    /// The remote peer must decode with [`Decoder::with_hasher`](crate::Decoder::with_hasher)
+    /// using the same hash function and key.
+    pub fn with_hasher(hasher: H) -> Self {
         Self {
-            entries: Default::default(),
+            entries: Vec::new(),
+            hasher,
         }
     }
+
+    /// Identifies the key used by this encoder, without revealing it.
+    pub fn key_id(&self) -> [u8; 16] {
+        self.hasher.key_id()
+    }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable + Copy> IntoIterator for Encoder<T> {
-    type Item = Symbol<T>;
-    type IntoIter = EncoderIter<T>;
+impl<T: Item, H: SymbolHasher, const W: usize> IntoIterator for Encoder<T, H, W> {
+    type Item = Symbol<T, W>;
+    type IntoIter = EncoderIter<T, H, W>;
 
     fn into_iter(mut self) -> Self::IntoIter {
+        // fails to compile for unsupported checksum widths.
+        let _ = Symbol::<T, W>::WIDTH;
+
         // entries must be unique.
         self.entries
-            .sort_unstable_by(|a, b| Ord::cmp(a.as_bytes(), b.as_bytes()));
-        self.entries.dedup_by(|a, b| a.as_bytes() == b.as_bytes());
+            .sort_unstable_by(|a, b| Ord::cmp(a.bytes(), b.bytes()));
+        self.entries.dedup_by(|a, b| a.bytes() == b.bytes());
 
         let mut heap = Vec::with_capacity(self.entries.len());
+        let mut by_checksum = BTreeSet::new();
         for (entry_index, value) in self.entries.iter().enumerate() {
-            let checksum = hash(value.as_bytes());
+            let checksum = self.hasher.hash(value.bytes());
             heap.push(Entry {
-                index: IndexGenerator::new(checksum),
+                index: IndexGenerator::new(&checksum),
                 entry_index,
                 checksum,
             });
+            by_checksum.insert((checksum, entry_index));
         }
 
         EncoderIter {
+            positions: (0..self.entries.len()).collect(),
             entries: self.entries,
             heap,
+            by_checksum,
             index: 0,
             compress: true,
+            hasher: self.hasher,
         }
     }
 }
 
-impl<T: IntoBytes + Immutable> Extend<T> for Encoder<T> {
+impl<T: Item, H: SymbolHasher, const W: usize> Extend<T> for Encoder<T, H, W> {
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         self.entries.extend(iter);
     }
 }
 
-impl<T: IntoBytes + Immutable> FromIterator<T> for Encoder<T> {
+impl<T: Item> FromIterator<T> for Encoder<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
         Encoder {
             entries: Vec::from_iter(iter),
+            hasher: Blake3::default(),
         }
     }
 }
 
 #[derive(Debug, Clone)]
-struct Entry {
+struct Entry<const W: usize> {
     index: IndexGenerator,
     entry_index: usize,
-    checksum: [u8; 16],
+    checksum: [u8; W],
 }
 
-impl PartialOrd for Entry {
+impl<const W: usize> PartialOrd for Entry<W> {
     fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         Some(Ord::cmp(self, other))
     }
 }
 
-impl PartialEq for Entry {
+impl<const W: usize> PartialEq for Entry<W> {
     fn eq(&self, other: &Self) -> bool {
         self.index.current() == other.index.current()
     }
 }
 
-impl Ord for Entry {
+impl<const W: usize> Ord for Entry<W> {
     fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         Ord::cmp(&self.index.current(), &other.index.current()).reverse()
     }
 }
 
-impl Eq for Entry {}
+impl<const W: usize> Eq for Entry<W> {}
 
-pub struct EncoderIter<T> {
+pub struct EncoderIter<T, H = Blake3, const W: usize = 16> {
     pub(crate) entries: Vec<T>,
-    heap: Vec<Entry>,
-    index: u64,
+    heap: Vec<Entry<W>>,
+    /// The position in `heap` of each entry.
+    positions: Vec<usize>,
+    /// Each entry's checksum and index in `entries`, to find entries without a scan.
+    by_checksum: BTreeSet<([u8; W], usize)>,
+    pub(crate) index: u64,
     compress: bool,
+    pub(crate) hasher: H,
 }
 
-impl<T> Default for EncoderIter<T> {
+impl<T, const W: usize> Default for EncoderIter<T, Blake3, W> {
     fn default() -> Self {
+        Self::empty(Blake3::default())
+    }
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Clone for EncoderIter<T, H, W> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.iter().map(Item::copy).collect(),
+            heap: self.heap.clone(),
+            positions: self.positions.clone(),
+            by_checksum: self.by_checksum.clone(),
+            index: self.index,
+            compress: self.compress,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<T, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
+    /// An encoder for the empty set, using `hasher`.
+    pub(crate) fn empty(hasher: H) -> Self {
         Self {
-            entries: Default::default(),
-            heap: Default::default(),
-            index: Default::default(),
+            entries: Vec::new(),
+            heap: Vec::new(),
+            positions: Vec::new(),
+            by_checksum: BTreeSet::new(),
+            index: 0,
             compress: false,
+            hasher,
         }
     }
+
+    /// Identifies the key used by this encoder, without revealing it.
+    pub fn key_id(&self) -> [u8; 16] {
+        self.hasher.key_id()
+    }
 }
 
-impl<T: IntoBytes + Immutable> EncoderIter<T> {
-    pub(crate) fn push_unchecked(&mut self, value: T, checksum: [u8; 16], index: IndexGenerator) {
+impl<T: Item, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
+    pub(crate) fn push_unchecked(&mut self, value: T, checksum: [u8; W], index: IndexGenerator) {
         let entry_index = self.entries.len();
         self.heap.push(Entry {
             entry_index,
             checksum,
             index,
         });
-        binaryheap::sift_up(&mut self.heap, 0, entry_index);
+        self.positions.push(entry_index);
+        self.by_checksum.insert((checksum, entry_index));
+        self.sift_up(entry_index);
         self.entries.push(value);
     }
+
+    // the heap operations keep `positions` up to date.
+
+    fn sift_up(&mut self, pos: usize) -> usize {
+        let positions = &mut self.positions;
+        binaryheap::sift_up_by(&mut self.heap, 0, pos, |e, i| positions[e.entry_index] = i)
+    }
+
+    fn sift_down(&mut self, pos: usize) {
+        let positions = &mut self.positions;
+        binaryheap::sift_down_by(&mut self.heap, pos, |e, i| positions[e.entry_index] = i)
+    }
+
+    fn rebuild(&mut self) {
+        let positions = &mut self.positions;
+        binaryheap::rebuild_by(&mut self.heap, |e, i| positions[e.entry_index] = i)
+    }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> EncoderIter<T> {
+impl<T: Item, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
     fn threshold(&self) -> u64 {
         if self.entries.len() < 2 {
             return 0;
@@ -133,7 +223,7 @@ impl<T: FromBytes + IntoBytes + Immutable> EncoderIter<T> {
     }
 
     #[cold]
-    fn update_many(&mut self) -> Symbol<T> {
+    fn update_many(&mut self) -> Symbol<T, W> {
         let mut s = Symbol::default();
 
         for p in self.heap.iter_mut() {
@@ -147,13 +237,13 @@ impl<T: FromBytes + IntoBytes + Immutable> EncoderIter<T> {
 
         // only build the binary heap when it's time to switch strategy
         if self.index == self.threshold() {
-            binaryheap::rebuild(&mut self.heap);
+            self.rebuild();
         }
 
         s
     }
 
-    fn update_few(&mut self) -> Symbol<T> {
+    fn update_few(&mut self) -> Symbol<T, W> {
         let mut s = Symbol::default();
 
         while let Some(p) = self.heap.first_mut() {
@@ -163,13 +253,175 @@ impl<T: FromBytes + IntoBytes + Immutable> EncoderIter<T> {
 
             s.add_entry(&self.entries[p.entry_index], &p.checksum);
             p.index.next();
-            binaryheap::sift_down(&mut self.heap, 0);
+            self.sift_down(0);
         }
 
         s
     }
 
-    pub(crate) fn must_next(&mut self) -> Symbol<T> {
+    /// Computes the symbol at `index` without advancing the iterator.
+    ///
+    /// This is useful for serving retransmissions without replaying the whole stream.
+    pub fn symbol_at(&self, index: u64) -> Symbol<T, W> {
+        let mut s = Symbol::default();
+
+        for p in &self.heap {
+            let mut gen = IndexGenerator::new(&p.checksum);
+            gen.skip_to(index);
+            if gen.current() == index {
+                s.add_entry(&self.entries[p.entry_index], &p.checksum);
+            }
+        }
+
+        if self.compress && index > 0 {
+            s.encode_count(index, self.entries.len() as i64);
+        }
+
+        s
+    }
+
+    /// Moves the iterator so that the next symbol produced is the symbol at `index`.
+    ///
+    /// Seeking can go both forwards and backwards, for instance to resume a sync from symbol N.
+    pub fn seek(&mut self, index: u64) {
+        for p in &mut self.heap {
+            p.index = IndexGenerator::new(&p.checksum);
+            p.index.skip_to(index);
+        }
+
+        self.index = index;
+
+        // the binary heap is normally built once we reach the threshold.
+        if self.index > self.threshold() {
+            self.rebuild();
+        }
+    }
+
+    /// Inserts `value` into the set being encoded.
+    ///
+    /// Future symbols will include `value`. Symbols that were already emitted are
+    /// reported as a list of `(index, correction)` pairs, where adding the correction
+    /// to the previously emitted symbol at that index produces the updated symbol.
+    ///
+    /// Returns `None` if `value` was already in the set.
+    pub fn insert(&mut self, value: T) -> Option<Vec<(u64, Symbol<T, W>)>> {
+        let checksum = self.hasher.hash(value.bytes());
+        if self.find_entry(&value, &checksum).is_some() {
+            return None;
+        }
+
+        let in_heap_mode = self.index > self.threshold();
+        let (corrections, index) = self.corrections(&value, &checksum, 1);
+        self.push_unchecked(value, checksum, index);
+
+        if !in_heap_mode && self.index > self.threshold() {
+            self.rebuild();
+        }
+
+        Some(corrections)
+    }
+
+    /// Removes `value` from the set being encoded.
+    ///
+    /// Future symbols will no longer include `value`. Symbols that were already emitted
+    /// are reported as a list of `(index, correction)` pairs, where adding the correction
+    /// to the previously emitted symbol at that index produces the updated symbol.
+    ///
+    /// Returns `None` if `value` was not in the set.
+    pub fn remove(&mut self, value: &T) -> Option<Vec<(u64, Symbol<T, W>)>> {
+        let checksum = self.hasher.hash(value.bytes());
+        let heap_index = self.find_entry(value, &checksum)?;
+        Some(self.remove_at(heap_index, value, &checksum))
+    }
+
+    /// Removes the entry at `heap_index`, as found by [`EncoderIter::find_entry`].
+    pub(crate) fn remove_at(
+        &mut self,
+        heap_index: usize,
+        value: &T,
+        checksum: &[u8; W],
+    ) -> Vec<(u64, Symbol<T, W>)> {
+        let in_heap_mode = self.index > self.threshold();
+        let (corrections, _) = self.corrections(value, checksum, -1);
+
+        let entry = self.heap.swap_remove(heap_index);
+        if heap_index < self.heap.len() {
+            self.positions[self.heap[heap_index].entry_index] = heap_index;
+            if self.sift_up(heap_index) == heap_index {
+                self.sift_down(heap_index);
+            }
+        }
+
+        // the last entry moves into the removed slot.
+        let last = self.entries.len() - 1;
+        self.entries.swap_remove(entry.entry_index);
+        self.positions.swap_remove(entry.entry_index);
+        self.by_checksum
+            .remove(&(entry.checksum, entry.entry_index));
+        if entry.entry_index != last {
+            let moved = &mut self.heap[self.positions[entry.entry_index]];
+            moved.entry_index = entry.entry_index;
+            self.by_checksum.remove(&(moved.checksum, last));
+            self.by_checksum.insert((moved.checksum, entry.entry_index));
+        }
+
+        if !in_heap_mode && self.index > self.threshold() {
+            self.rebuild();
+        }
+
+        corrections
+    }
+
+    /// Returns the position in the heap of `value`, if it is in the set.
+    pub(crate) fn find_entry(&self, value: &T, checksum: &[u8; W]) -> Option<usize> {
+        self.by_checksum
+            .range((*checksum, 0)..=(*checksum, usize::MAX))
+            .find(|&&(_, i)| self.entries[i].bytes() == value.bytes())
+            .map(|&(_, i)| self.positions[i])
+    }
+
+    /// Computes the corrections for all emitted symbols when `value` is
+    /// added to (`sign = 1`) or removed from (`sign = -1`) the set.
+    ///
+    /// Also returns the index generator for `value`, positioned at the next symbol.
+    fn corrections(
+        &self,
+        value: &T,
+        checksum: &[u8; W],
+        sign: i64,
+    ) -> (Vec<(u64, Symbol<T, W>)>, IndexGenerator) {
+        let n = self.entries.len() as i64;
+        let new_n = n + sign;
+
+        let mut corrections = Vec::new();
+        let mut index = IndexGenerator::new(checksum);
+        for i in 0..self.index {
+            let mut s = Symbol::<T, W>::default();
+            let mut d = 0;
+            if index.current() <= i {
+                s.sum.xor(value);
+                xor_mut(&mut s.checksum, checksum);
+                d = sign;
+                index.next();
+            }
+
+            // the encoded count depends on the size of the set.
+            let count = if self.compress && i > 0 {
+                expected_count(i, new_n) - expected_count(i, n) - d
+            } else {
+                d
+            };
+            s.count.set(count);
+
+            if count != 0 || d != 0 {
+                corrections.push((i, s));
+            }
+        }
+
+        (corrections, index)
+    }
+
+    pub(crate) fn must_next(&mut self) -> Symbol<T, W> {
         let mut s = if self.index <= self.threshold() {
             self.update_many()
         } else {
@@ -177,7 +429,7 @@ impl<T: FromBytes + IntoBytes + Immutable> EncoderIter<T> {
         };
 
         if self.compress && self.index > 0 {
-            s.encode_count(self.index, self.entries.len());
+            s.encode_count(self.index, self.entries.len() as i64);
         }
 
         self.index += 1;
@@ -185,10 +437,257 @@ impl<T: FromBytes + IntoBytes + Immutable> EncoderIter<T> {
     }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable + Copy> Iterator for EncoderIter<T> {
-    type Item = Symbol<T>;
+impl<T: Item, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
+    /// Overwrites `symbols` with the next `symbols.len()` symbols.
+    ///
+    /// This makes one pass over the entries that map into the window of symbols,
+    /// rather than one heap operation per entry per symbol as [`Iterator::next`] does.
+    pub fn fill(&mut self, symbols: &mut [Symbol<T, W>]) {
+        let start = self.index;
+        let end = start + symbols.len() as u64;
+        symbols.fill_with(Symbol::default);
+
+        if start <= self.threshold() {
+            // most entries map into the first few symbols, so visit all of them.
+            for p in &mut self.heap {
+                add_to_window(symbols, start, &self.entries, p);
+            }
+            if end > self.threshold() {
+                self.rebuild();
+            }
+        } else {
+            while let Some(p) = self.heap.first_mut() {
+                if p.index.current() >= end {
+                    break;
+                }
+                add_to_window(symbols, start, &self.entries, p);
+                self.sift_down(0);
+            }
+        }
+
+        self.finish_window(symbols);
+    }
+
+    /// Produces the next `n` symbols in one batch. See [`EncoderIter::fill`].
+    pub fn next_chunk(&mut self, n: usize) -> Vec<Symbol<T, W>> {
+        let mut symbols = Vec::with_capacity(n);
+        symbols.resize_with(n, Symbol::default);
+        self.fill(&mut symbols);
+        symbols
+    }
+
+    /// Encodes the counts of a window of symbols starting at the current index,
+    /// and moves past it.
+    fn finish_window(&mut self, symbols: &mut [Symbol<T, W>]) {
+        let start = self.index;
+        if self.compress {
+            for (i, s) in (start..).zip(symbols.iter_mut()) {
+                if i > 0 {
+                    s.encode_count(i, self.entries.len() as i64);
+                }
+            }
+        }
+        self.index = start + symbols.len() as u64;
+    }
+}
+
+/// Adds the entry of `p` to each symbol of the window starting at `start` that it maps to,
+/// leaving its index generator positioned after the window.
+fn add_to_window<T: Item, const W: usize>(
+    symbols: &mut [Symbol<T, W>],
+    start: u64,
+    entries: &[T],
+    p: &mut Entry<W>,
+) {
+    let end = start + symbols.len() as u64;
+    while p.index.current() < end {
+        let s = &mut symbols[(p.index.current() - start) as usize];
+        s.add_entry(&entries[p.entry_index], &p.checksum);
+        p.index.next();
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Item + Send + Sync, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
+    /// Produces the next `n` symbols, using all threads in the rayon thread pool.
+    ///
+    /// The entries are partitioned across threads, and each thread sums its entries
+    /// into a partial block of `n` symbols. The blocks are then added together.
+    /// This is mostly useful for the first few symbols of large sets, which hold
+    /// a large fraction of all entries.
+    pub fn par_next_chunk(&mut self, n: usize) -> Vec<Symbol<T, W>> {
+        use rayon::prelude::*;
+
+        let start = self.index;
+        let entries = &self.entries;
+        let block = || {
+            (0..n)
+                .map(|_| Symbol::<T, W>::default())
+                .collect::<Vec<_>>()
+        };
+
+        let mut symbols = self
+            .heap
+            .par_iter_mut()
+            .fold(block, |mut symbols, p| {
+                add_to_window(&mut symbols, start, entries, p);
+                symbols
+            })
+            .reduce(block, |mut a, b| {
+                for (a, b) in core::iter::zip(&mut a, b) {
+                    *a += b;
+                }
+                a
+            });
+
+        self.finish_window(&mut symbols);
+        if self.index > self.threshold() {
+            self.rebuild();
+        }
+
+        symbols
+    }
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Iterator for EncoderIter<T, H, W> {
+    type Item = Symbol<T, W>;
 
     fn next(&mut self) -> Option<Self::Item> {
         Some(self.must_next())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{Encoder, Symbol};
+
+    fn symbols(entries: &[u64], n: usize) -> Vec<Symbol<u64>> {
+        Encoder::from_iter(entries.iter().copied())
+            .into_iter()
+            .take(n)
+            .collect()
+    }
+
+    fn apply(emitted: &mut [Symbol<u64>], corrections: Vec<(u64, Symbol<u64>)>) {
+        for (i, c) in corrections {
+            emitted[i as usize] += c;
+        }
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let before: Vec<u64> = (0..50).collect();
+        let after: Vec<u64> = (0..50).filter(|&x| x != 17).chain([100, 101]).collect();
+
+        let mut iter = Encoder::from_iter(before.iter().copied()).into_iter();
+        let mut emitted: Vec<_> = iter.by_ref().take(30).collect();
+
+        apply(&mut emitted, iter.insert(100).unwrap());
+        apply(&mut emitted, iter.remove(&17).unwrap());
+        assert!(iter.insert(100).is_none());
+        assert!(iter.remove(&17).is_none());
+        apply(&mut emitted, iter.insert(101).unwrap());
+
+        emitted.extend(iter.take(30));
+        assert_eq!(&emitted, &symbols(&after, 60));
+    }
+
+    #[test]
+    fn many_updates() {
+        let mut iter = Encoder::from_iter(0..500u64).into_iter();
+        let mut emitted: Vec<_> = iter.by_ref().take(40).collect();
+
+        for x in 0..100 {
+            apply(&mut emitted, iter.remove(&(2 * x)).unwrap());
+            apply(&mut emitted, iter.insert(1000 + x).unwrap());
+            emitted.push(iter.next().unwrap());
+
+            for (i, e) in iter.heap.iter().enumerate() {
+                assert_eq!(iter.positions[e.entry_index], i);
+            }
+            assert_eq!(iter.by_checksum.len(), iter.entries.len());
+        }
+
+        let after: Vec<u64> = (0..500)
+            .filter(|x| x % 2 == 1 || *x >= 200)
+            .chain(1000..1100)
+            .collect();
+        assert_eq!(&emitted, &symbols(&after, 140));
+    }
+
+    #[test]
+    fn symbol_at_and_seek() {
+        let entries: Vec<u64> = (0..100).collect();
+        let expected = symbols(&entries, 200);
+
+        let mut iter = Encoder::from_iter(entries.iter().copied()).into_iter();
+        let random_access: Vec<_> = (0..200).map(|i| iter.symbol_at(i)).collect();
+        assert_eq!(&random_access, &expected);
+
+        iter.seek(150);
+        assert_eq!(
+            &iter.by_ref().take(50).collect::<Vec<_>>(),
+            &expected[150..],
+        );
+
+        iter.seek(3);
+        assert_eq!(
+            &iter.by_ref().take(50).collect::<Vec<_>>(),
+            &expected[3..53],
+        );
+
+        iter.seek(0);
+        assert_eq!(&iter.take(200).collect::<Vec<_>>(), &expected);
+    }
+
+    #[test]
+    fn insert_crosses_threshold() {
+        // the threshold for switching to the binary heap grows with the set size.
+        let mut iter = Encoder::from_iter([1u64, 2, 3]).into_iter();
+        let mut emitted: Vec<_> = iter.by_ref().take(5).collect();
+
+        for x in 4..40 {
+            apply(&mut emitted, iter.insert(x).unwrap());
+        }
+        for x in 4..38 {
+            apply(&mut emitted, iter.remove(&x).unwrap());
+        }
+
+        emitted.extend(iter.take(40));
+        let after: Vec<u64> = [1, 2, 3].into_iter().chain(38..40).collect();
+        assert_eq!(&emitted, &symbols(&after, 45));
+    }
+
+    #[test]
+    fn next_chunk() {
+        let entries: Vec<u64> = (0..1000).collect();
+        let expected = symbols(&entries, 200);
+
+        // cross the threshold within a chunk, and then chunk in heap mode.
+        let mut iter = Encoder::from_iter(entries).into_iter();
+        let mut actual = iter.next_chunk(5);
+        actual.extend(iter.next_chunk(50));
+        actual.extend(iter.by_ref().take(5));
+        let mut rest = vec![Symbol::default(); 140];
+        iter.fill(&mut rest);
+        actual.extend(rest);
+
+        assert_eq!(&actual, &expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_next_chunk() {
+        let entries: Vec<u64> = (0..10_000).collect();
+        let expected = symbols(&entries, 100);
+
+        let mut iter = Encoder::from_iter(entries).into_iter();
+        let mut actual = iter.par_next_chunk(10);
+        actual.extend(iter.par_next_chunk(60));
+        actual.extend(iter.by_ref().take(30));
+
+        assert_eq!(&actual, &expected);
+    }
+}
@@ -0,0 +1,85 @@
+use alloc::vec::Vec;
+
+use zerocopy::{little_endian, FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+use crate::{set_difference, Encoder, Item, Symbol, SymbolHasher};
+
+/// The number of strata. Differences up to about `2^STRATA` entries can be estimated.
+const STRATA: usize = 24;
+
+/// The number of symbols per stratum, which can decode a difference of about 20 entries.
+const CELLS: usize = 32;
+
+/// Estimates the size of the difference between two sets before reconciling them.
+///
+/// This is a strata estimator. Each entry is hashed to an id, and assigned to
+/// stratum `i` with probability `2^-(i + 1)`. Each stratum holds the first few
+/// coded symbols of its ids. Strata are decoded from the sparsest down, until one
+/// fails to decode, and the decoded count is scaled up by the sampling rate.
+///
+/// An estimator is about 24 KiB, and can be sent as is with [`IntoBytes`].
+/// Both peers must use the same key.
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[repr(C)]
+pub struct Estimator {
+    strata: [[Symbol<little_endian::U64>; CELLS]; STRATA],
+}
+
+impl Estimator {
+    /// Creates an estimator for the entries of `encoder`.
+    pub fn new<T: Item, H: SymbolHasher, const W: usize>(encoder: &Encoder<T, H, W>) -> Self {
+        let mut ids: [Vec<little_endian::U64>; STRATA] = Default::default();
+        for entry in &encoder.entries {
+            let h = encoder.hasher.hash::<16>(entry.bytes());
+            let id = u64::from_le_bytes(h[..8].try_into().unwrap());
+            let level = u64::from_le_bytes(h[8..].try_into().unwrap()).trailing_zeros();
+            ids[(level as usize).min(STRATA - 1)].push(little_endian::U64::new(id));
+        }
+
+        let mut estimator = Self::new_zeroed();
+        for (stratum, ids) in core::iter::zip(&mut estimator.strata, ids) {
+            for (cell, symbol) in core::iter::zip(stratum, Encoder::from_iter(ids)) {
+                *cell = symbol;
+            }
+        }
+        estimator
+    }
+
+    /// Estimates the number of entries in the difference between this set and the `other` set.
+    pub fn estimate(&self, other: &Estimator) -> u64 {
+        let mut count = 0;
+        for i in (0..STRATA).rev() {
+            match set_difference(self.strata[i], other.strata[i]) {
+                Some((a, b)) => count += (a.len() + b.len()) as u64,
+                None => return count.max(1) << (i + 1),
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Estimator;
+    use crate::Encoder;
+
+    fn estimate(d: u64) -> u64 {
+        let a = Encoder::from_iter(0..20_000u64);
+        let b = Encoder::from_iter(d..20_000 + d);
+        Estimator::new(&a).estimate(&Estimator::new(&b))
+    }
+
+    #[test]
+    fn exact_for_small_differences() {
+        assert_eq!(estimate(0), 0);
+        assert_eq!(estimate(3), 6);
+    }
+
+    #[test]
+    fn within_factor_of_two() {
+        for d in [200, 2_000, 8_000] {
+            let estimate = estimate(d);
+            assert!(d < estimate && estimate < 4 * d, "{d}: {estimate}");
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::Key;
+
+/// The hash scheme used for checksums and index sequences.
+pub const HASH_BLAKE3: u8 = 0;
+/// The hash scheme used for checksums and index sequences, when a key is configured.
+pub const HASH_BLAKE3_KEYED: u8 = 1;
+/// The hash scheme for XXH3.
+#[cfg(any(feature = "std", feature = "xxh3"))]
+pub const HASH_XXH3: u8 = 2;
+/// The hash scheme for SipHash.
+#[cfg(any(feature = "std", feature = "siphash"))]
+pub const HASH_SIPHASH: u8 = 3;
+
+/// Hashes entries into the checksums stored in symbols.
+///
+/// The checksum also seeds the sequence of symbols that each entry maps to, so both
+/// peers must use the same hash function and key. [`Blake3`] is the default. It is
+/// safe against peers crafting colliding entries when keyed, at the cost of throughput.
+/// Faster non-cryptographic hashes are available behind the `xxh3` and `siphash` features,
+/// for trusted networks.
+pub trait SymbolHasher: Clone {
+    /// Hashes the bytes of an entry into `out`, which is as long as the checksum.
+    fn fill(&self, bytes: &[u8], out: &mut [u8]);
+
+    /// Hashes the bytes of an entry into its checksum.
+    fn hash<const W: usize>(&self, bytes: &[u8]) -> [u8; W] {
+        let mut out = [0; W];
+        self.fill(bytes, &mut out);
+        out
+    }
+
+    /// Identifies the hash function in [`wire::Header`](crate::wire::Header).
+    fn scheme(&self) -> u8;
+
+    /// Identifies the hash function and key, without revealing the key.
+    fn key_id(&self) -> [u8; 16] {
+        self.hash(&[])
+    }
+}
+
+/// BLAKE3, truncated to the checksum width, with an optional secret key.
+#[derive(Debug, Clone, Default)]
+pub struct Blake3 {
+    key: Option<Key>,
+}
+
+impl Blake3 {
+    /// BLAKE3 keyed hashing. See [`Key`].
+    pub fn with_key(key: Key) -> Self {
+        Self { key: Some(key) }
+    }
+}
+
+impl SymbolHasher for Blake3 {
+    fn fill(&self, bytes: &[u8], out: &mut [u8]) {
+        let mut hasher = match &self.key {
+            Some(key) => blake3::Hasher::new_keyed(key),
+            None => blake3::Hasher::new(),
+        };
+        hasher.update(bytes);
+        hasher.finalize_xof().fill(out);
+    }
+
+    fn scheme(&self) -> u8 {
+        if self.key.is_some() {
+            HASH_BLAKE3_KEYED
+        } else {
+            HASH_BLAKE3
+        }
+    }
+}
+
+/// 128-bit XXH3 with a seed.
+///
+/// Checksums wider than 16 bytes are filled with the hashes for the next seeds.
+/// This is much faster than BLAKE3 for small entries, but the seed does not protect
+/// against crafted entries.
+#[cfg(feature = "xxh3")]
+#[derive(Debug, Clone, Default)]
+pub struct Xxh3 {
+    seed: u64,
+}
+
+#[cfg(feature = "xxh3")]
+impl Xxh3 {
+    /// XXH3 seeded with `seed`. Both peers must use the same seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+#[cfg(feature = "xxh3")]
+impl SymbolHasher for Xxh3 {
+    fn fill(&self, bytes: &[u8], out: &mut [u8]) {
+        for (i, block) in out.chunks_mut(16).enumerate() {
+            let seed = self.seed.wrapping_add(i as u64);
+            let hash = xxhash_rust::xxh3::xxh3_128_with_seed(bytes, seed).to_le_bytes();
+            block.copy_from_slice(&hash[..block.len()]);
+        }
+    }
+
+    fn scheme(&self) -> u8 {
+        HASH_XXH3
+    }
+}
+
+/// 128-bit SipHash-1-3 with a secret key.
+///
+/// Checksums wider than 16 bytes are filled with the hashes for the next keys.
+/// This is a keyed hash that is faster than BLAKE3 for small entries, with weaker
+/// guarantees against crafted entries.
+#[cfg(feature = "siphash")]
+#[derive(Debug, Clone)]
+pub struct SipHash {
+    hasher: siphasher::sip128::SipHasher13,
+}
+
+#[cfg(feature = "siphash")]
+impl SipHash {
+    /// SipHash keyed with a secret `key`, shared by both peers.
+    pub fn with_key(key: [u8; 16]) -> Self {
+        Self {
+            hasher: siphasher::sip128::SipHasher13::new_with_key(&key),
+        }
+    }
+}
+
+#[cfg(feature = "siphash")]
+impl SymbolHasher for SipHash {
+    fn fill(&self, bytes: &[u8], out: &mut [u8]) {
+        let (k0, k1) = self.hasher.keys();
+        for (i, block) in out.chunks_mut(16).enumerate() {
+            let hasher =
+                siphasher::sip128::SipHasher13::new_with_keys(k0.wrapping_add(i as u64), k1);
+            block.copy_from_slice(&hasher.hash(bytes).as_bytes()[..block.len()]);
+        }
+    }
+
+    fn scheme(&self) -> u8 {
+        HASH_SIPHASH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{Blake3, SymbolHasher};
+
+    fn difference<H: SymbolHasher>(hasher: H) -> (Vec<u64>, Vec<u64>) {
+        crate::tests::difference::<H, 16>(hasher, 0..100, 5..105)
+    }
+
+    #[test]
+    fn blake3() {
+        let expected = (Vec::from_iter(0..5), Vec::from_iter(100..105));
+        assert_eq!(difference(Blake3::default()), expected);
+        assert_eq!(difference(Blake3::with_key([1; 32])), expected);
+    }
+
+    #[cfg(feature = "xxh3")]
+    #[test]
+    fn xxh3() {
+        let hasher = super::Xxh3::with_seed(7);
+        assert_ne!(hasher.key_id(), Blake3::default().key_id());
+        assert_eq!(
+            difference(hasher),
+            (Vec::from_iter(0..5), Vec::from_iter(100..105))
+        );
+    }
+
+    #[cfg(feature = "siphash")]
+    #[test]
+    fn siphash() {
+        let hasher = super::SipHash::with_key([1; 16]);
+        assert_ne!(hasher.key_id(), super::SipHash::with_key([2; 16]).key_id());
+        assert_eq!(
+            difference(hasher),
+            (Vec::from_iter(0..5), Vec::from_iter(100..105))
+        );
+    }
+}
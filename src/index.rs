@@ -8,11 +8,19 @@ pub(crate) struct IndexGenerator {
 }
 
 impl IndexGenerator {
-    pub(crate) fn new(checksum: [u8; 16]) -> Self {
-        Self {
-            rng: SeedableRng::from_seed(checksum),
-            index: 0,
-        }
+    pub(crate) fn new(checksum: &[u8]) -> Self {
+        // checksums narrower than the seed are expanded, rather than zero padded,
+        // so that every seed bit depends on the checksum.
+        let rng = match checksum.get(..16) {
+            Some(seed) => SeedableRng::from_seed(seed.try_into().unwrap()),
+            None => {
+                let mut seed = [0; 8];
+                let n = checksum.len().min(8);
+                seed[..n].copy_from_slice(&checksum[..n]);
+                SeedableRng::seed_from_u64(u64::from_le_bytes(seed))
+            }
+        };
+        Self { rng, index: 0 }
     }
 
     pub(crate) fn current(&self) -> u64 {
@@ -22,6 +30,13 @@ impl IndexGenerator {
     pub(crate) fn next(&mut self) {
         self.index += libm::ceil(c_inv(self.index as f64, self.rng.next_u64())) as u64
     }
+
+    /// Advances to the first index at or after `index`.
+    pub(crate) fn skip_to(&mut self, index: u64) {
+        while self.index < index {
+            self.next();
+        }
+    }
 }
 
 fn c_inv(i: f64, r: u64) -> f64 {
@@ -33,11 +48,18 @@ pub(crate) fn p(i: f64) -> f64 {
     (1.0 + 0.5 * i).recip()
 }
 
+/// The expected number of entries mapped to cell `i` when the set has `n` entries.
+///
+/// `n` is negative for the signed difference of two sets.
+pub(crate) fn expected_count(i: u64, n: i64) -> i64 {
+    libm::ceil(p(i as f64) * (n as f64)) as i64
+}
+
 #[cfg(test)]
 mod tests {
     use zerocopy::IntoBytes;
 
-    use crate::hash;
+    use crate::{Blake3, SymbolHasher};
 
     use super::*;
     use alloc::collections::BTreeMap;
@@ -49,7 +71,7 @@ mod tests {
         const L: u64 = 1000;
 
         for i in 0..N {
-            let mut gen = IndexGenerator::new(hash(i.as_bytes()));
+            let mut gen = IndexGenerator::new(&Blake3::default().hash::<16>(i.as_bytes()));
             while gen.current() < L {
                 *map.entry(gen.current()).or_default() += 1;
                 gen.next();
@@ -0,0 +1,127 @@
+use zerocopy::{little_endian, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+use crate::{Blake3, EncoderIter, Item, Symbol, SymbolHasher};
+
+/// How often [`Indexed`] repeats the first symbol, which is needed to detect
+/// that decoding is complete.
+const FIRST_SYMBOL_INTERVAL: u64 = 32;
+
+/// A symbol tagged with its index in the stream, for transports that can lose
+/// or reorder symbols, such as UDP.
+///
+/// Counts are not delta encoded, since decoding them would need the first symbol.
+/// Decode with [`Decoder::push_indexed`](crate::Decoder::push_indexed).
+#[derive(Debug, Clone, Copy, FromBytes, Immutable, IntoBytes, Unaligned, KnownLayout)]
+#[repr(C)]
+pub struct IndexedSymbol<T, const W: usize = 16> {
+    pub(crate) index: little_endian::U64,
+    pub(crate) symbol: Symbol<T, W>,
+}
+
+impl<T, const W: usize> IndexedSymbol<T, W> {
+    /// The index of this symbol in the stream.
+    pub fn index(&self) -> u64 {
+        self.index.get()
+    }
+}
+
+/// An iterator of [`IndexedSymbol`]s, created by [`EncoderIter::indexed`].
+pub struct Indexed<T, H = Blake3, const W: usize = 16> {
+    inner: EncoderIter<T, H, W>,
+    first: Symbol<T, W>,
+    sent: u64,
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
+    /// Converts this iterator into an iterator of indexed symbols.
+    ///
+    /// The first symbol is repeated every few symbols, so that losing it does
+    /// not stall decoding.
+    pub fn indexed(self) -> Indexed<T, H, W> {
+        Indexed {
+            first: self.symbol_at(0),
+            inner: self,
+            sent: 0,
+        }
+    }
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Iterator for Indexed<T, H, W> {
+    type Item = IndexedSymbol<T, W>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.sent += 1;
+        if self.sent.is_multiple_of(FIRST_SYMBOL_INTERVAL) {
+            return Some(IndexedSymbol {
+                index: little_endian::U64::new(0),
+                symbol: self.first.copy(),
+            });
+        }
+
+        let index = self.inner.index;
+        let mut symbol = self.inner.must_next();
+        if index > 0 {
+            symbol.decode_count(index, self.inner.entries.len() as i64);
+        }
+
+        Some(IndexedSymbol {
+            index: little_endian::U64::new(index),
+            symbol,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use zerocopy::little_endian;
+
+    use crate::{tests::sorted, DecodeError, Decoder, Encoder};
+
+    #[test]
+    fn lossy() {
+        let remote = Encoder::from_iter(0..1000u64).into_iter().indexed();
+        let mut decoder = Decoder::with_local(Encoder::from_iter(50..1050u64));
+
+        // lose the first symbol and every third one, and reverse pairs.
+        let mut symbols: Vec<_> = remote.take(1000).collect();
+        for pair in symbols.chunks_mut(2) {
+            pair.reverse();
+        }
+        let mut used = 0;
+        for (i, symbol) in symbols.into_iter().enumerate() {
+            if symbol.index() == 0 && i < 2 || i.is_multiple_of(3) {
+                continue;
+            }
+            decoder.push_indexed(symbol).unwrap();
+            used += 1;
+            if decoder.is_complete() {
+                break;
+            }
+        }
+        assert!(decoder.is_complete(), "{used}");
+
+        let (remote, local) = decoder.consume();
+        assert_eq!(sorted(remote), Vec::from_iter(0..50));
+        assert_eq!(sorted(local), Vec::from_iter(1000..1050));
+    }
+
+    #[test]
+    fn index_too_far() {
+        let mut remote = Encoder::from_iter(0..10u64).into_iter().indexed();
+        let mut decoder = Decoder::with_local(Encoder::from_iter(0..10u64));
+        decoder.set_max_gap(100);
+
+        let mut symbol = remote.next().unwrap();
+        symbol.index = little_endian::U64::new(1 << 40);
+        assert_eq!(
+            decoder.push_indexed(symbol),
+            Err(DecodeError::IndexTooFar { index: 1 << 40 })
+        );
+
+        let mut symbol = remote.next().unwrap();
+        symbol.index = little_endian::U64::new(100);
+        decoder.push_indexed(symbol).unwrap();
+    }
+}
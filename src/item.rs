@@ -0,0 +1,163 @@
+use alloc::vec::Vec;
+
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::{xor_mut, Decoder, Encoder, Symbol};
+
+/// A value that can be encoded into a [`Symbol`](crate::Symbol).
+///
+/// This is implemented for all fixed size zerocopy types, and for [`VarBytes`].
+pub trait Item {
+    /// The empty sum.
+    fn zero() -> Self;
+
+    /// XORs `other` into this sum.
+    fn xor(&mut self, other: &Self);
+
+    /// The bytes of this value, used for hashing and equality.
+    fn bytes(&self) -> &[u8];
+
+    /// Copies this value.
+    fn copy(&self) -> Self;
+}
+
+impl<T: FromBytes + IntoBytes + Immutable> Item for T {
+    fn zero() -> Self {
+        T::new_zeroed()
+    }
+
+    fn xor(&mut self, other: &Self) {
+        xor_mut(self, other);
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn copy(&self) -> Self {
+        T::read_from_bytes(self.as_bytes()).unwrap()
+    }
+}
+
+/// An [`Encoder`] of variable length byte strings.
+pub type VarEncoder = Encoder<VarBytes>;
+
+/// A [`Decoder`] of variable length byte strings.
+pub type VarDecoder = Decoder<VarBytes>;
+
+/// A [`Symbol`] of variable length byte strings.
+pub type VarSymbol = Symbol<VarBytes>;
+
+const LEN: usize = core::mem::size_of::<u64>();
+
+/// A variable length byte string.
+///
+/// The sum of many byte strings is length-prefixed and zero padded to the longest string,
+/// so the length is also covered by the checksum.
+#[derive(Clone)]
+pub struct VarBytes {
+    /// The little endian length, followed by the data and any zero padding.
+    buf: Vec<u8>,
+}
+
+impl VarBytes {
+    fn len_prefix(&self) -> Option<usize> {
+        let len = self.buf.get(..LEN)?;
+        usize::try_from(u64::from_le_bytes(len.try_into().unwrap())).ok()
+    }
+
+    /// The data of this byte string.
+    pub fn as_slice(&self) -> &[u8] {
+        self.bytes().get(LEN..).unwrap_or_default()
+    }
+
+    /// Copies the data of this byte string into a new `Vec`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.as_slice().to_vec()
+    }
+}
+
+impl Item for VarBytes {
+    fn zero() -> Self {
+        VarBytes { buf: Vec::new() }
+    }
+
+    fn xor(&mut self, other: &Self) {
+        if self.buf.len() < other.buf.len() {
+            self.buf.resize(other.buf.len(), 0);
+        }
+        xor_mut(&mut self.buf[..other.buf.len()], &other.buf[..]);
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // a sum of a single byte string only has zeros as padding.
+        // otherwise, the whole sum is returned so the checksum does not match.
+        match self.len_prefix() {
+            Some(len) if len <= self.buf.len() - LEN => {
+                let (data, padding) = self.buf.split_at(LEN + len);
+                if padding.iter().all(|&b| b == 0) {
+                    data
+                } else {
+                    &self.buf
+                }
+            }
+            _ => &self.buf,
+        }
+    }
+
+    fn copy(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl From<&[u8]> for VarBytes {
+    fn from(data: &[u8]) -> Self {
+        let mut buf = Vec::with_capacity(LEN + data.len());
+        buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        buf.extend_from_slice(data);
+        VarBytes { buf }
+    }
+}
+
+impl From<Vec<u8>> for VarBytes {
+    fn from(data: Vec<u8>) -> Self {
+        VarBytes::from(&data[..])
+    }
+}
+
+impl PartialEq for VarBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes() == other.bytes()
+    }
+}
+
+impl Eq for VarBytes {}
+
+impl core::fmt::Debug for VarBytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("VarBytes").field(&self.as_slice()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{set_difference, tests::sorted, VarBytes, VarEncoder};
+
+    #[test]
+    fn var_bytes() {
+        let remote: [&[u8]; 5] = [b"", b"hello", b"ab", b"a much longer string", b"x"];
+        let local: [&[u8]; 5] = [b"", b"hello", b"ab\0", b"short", b"x"];
+
+        let (remote, local) = set_difference(
+            VarEncoder::from_iter(remote.map(VarBytes::from)),
+            VarEncoder::from_iter(local.map(VarBytes::from)),
+        )
+        .unwrap();
+
+        let remote = sorted(remote.iter().map(VarBytes::to_vec).collect());
+        let local = sorted(local.iter().map(VarBytes::to_vec).collect());
+
+        assert_eq!(remote, [b"a much longer string".to_vec(), b"ab".to_vec()]);
+        assert_eq!(local, [b"ab\0".to_vec(), b"short".to_vec()]);
+    }
+}
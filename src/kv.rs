@@ -0,0 +1,166 @@
+use alloc::vec::Vec;
+
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::{decoder::decode_with, Decoder, Encoder, Symbol};
+
+/// A key-value pair of a map.
+///
+/// A key whose value differs between the peers is decoded once from each side,
+/// and the two pairs are then matched up by key.
+#[derive(Clone, Copy, FromBytes, Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct KeyValue<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: FromBytes + IntoBytes + Immutable, V: FromBytes + IntoBytes + Immutable> KeyValue<K, V> {
+    /// Pairs `key` with `value`.
+    pub fn new(key: K, value: V) -> Self {
+        Self { key, value }
+    }
+
+    /// The key.
+    pub fn key(&self) -> K {
+        K::read_from_prefix(self.as_bytes()).unwrap().0
+    }
+
+    /// The value.
+    pub fn value(&self) -> V {
+        V::read_from_suffix(self.as_bytes()).unwrap().1
+    }
+
+    fn key_bytes(&self) -> &[u8] {
+        &self.as_bytes()[..core::mem::size_of::<K>()]
+    }
+}
+
+/// An [`Encoder`] of key-value pairs.
+pub type KvEncoder<K, V> = Encoder<KeyValue<K, V>>;
+
+/// A [`Decoder`] of key-value pairs.
+pub type KvDecoder<K, V> = Decoder<KeyValue<K, V>>;
+
+/// The difference between a remote and a local map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvDifference<K, V> {
+    /// Pairs where the key is only in the remote map.
+    pub added: Vec<(K, V)>,
+    /// Pairs where the key is only in the local map.
+    pub removed: Vec<(K, V)>,
+    /// Keys that are in both maps, but with different values.
+    pub changed: Vec<Changed<K, V>>,
+}
+
+/// A key that has a different value in the remote and local maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changed<K, V> {
+    pub key: K,
+    /// The value in the remote map.
+    pub remote: V,
+    /// The value in the local map.
+    pub local: V,
+}
+
+impl<K: FromBytes + IntoBytes + Immutable, V: FromBytes + IntoBytes + Immutable>
+    Encoder<KeyValue<K, V>>
+{
+    /// Creates an encoder of a map, from its key-value pairs.
+    ///
+    /// Keys must be unique.
+    pub fn from_pairs(iter: impl IntoIterator<Item = (K, V)>) -> Self {
+        Encoder::from_iter(iter.into_iter().map(|(k, v)| KeyValue::new(k, v)))
+    }
+}
+
+impl<K: FromBytes + IntoBytes + Immutable, V: FromBytes + IntoBytes + Immutable>
+    Decoder<KeyValue<K, V>>
+{
+    /// Consumes the decoder, returning the difference between the remote and local maps.
+    pub fn consume_kv(self) -> KvDifference<K, V> {
+        let (remote, local) = self.consume();
+        let (remote, local, both) = join(remote, local, KeyValue::key_bytes);
+
+        KvDifference {
+            added: remote.iter().map(|kv| (kv.key(), kv.value())).collect(),
+            removed: local.iter().map(|kv| (kv.key(), kv.value())).collect(),
+            changed: both
+                .iter()
+                .map(|(remote, local)| Changed {
+                    key: remote.key(),
+                    remote: remote.value(),
+                    local: local.value(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Decodes the difference between the `remote` and `local` maps.
+///
+/// Returns `None` if either stream ends before decoding is complete,
+/// or if the streams are found to be corrupted.
+pub fn kv_difference<K: FromBytes + IntoBytes + Immutable, V: FromBytes + IntoBytes + Immutable>(
+    remote: impl IntoIterator<Item = Symbol<KeyValue<K, V>>>,
+    local: impl IntoIterator<Item = Symbol<KeyValue<K, V>>>,
+) -> Option<KvDifference<K, V>> {
+    decode_with(remote, local, Decoder::consume_kv)
+}
+
+/// Matches up the remote and local entries that share the same key.
+///
+/// Returns the remote only entries, the local only entries, and the matched pairs.
+pub(crate) fn join<T>(
+    mut remote: Vec<T>,
+    mut local: Vec<T>,
+    key: fn(&T) -> &[u8],
+) -> (Vec<T>, Vec<T>, Vec<(T, T)>) {
+    remote.sort_unstable_by(|a, b| Ord::cmp(key(a), key(b)));
+    local.sort_unstable_by(|a, b| Ord::cmp(key(a), key(b)));
+
+    let mut remote_only = Vec::new();
+    let mut local_only = Vec::new();
+    let mut both = Vec::new();
+
+    let mut remote = remote.into_iter().peekable();
+    let mut local = local.into_iter().peekable();
+    loop {
+        let ordering = match (remote.peek(), local.peek()) {
+            (Some(r), Some(l)) => Ord::cmp(key(r), key(l)),
+            (Some(_), None) => core::cmp::Ordering::Less,
+            (None, Some(_)) => core::cmp::Ordering::Greater,
+            (None, None) => break,
+        };
+        match ordering {
+            core::cmp::Ordering::Less => remote_only.extend(remote.next()),
+            core::cmp::Ordering::Greater => local_only.extend(local.next()),
+            core::cmp::Ordering::Equal => both.extend(remote.next().zip(local.next())),
+        }
+    }
+
+    (remote_only, local_only, both)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{kv_difference, Changed, KvEncoder};
+
+    #[test]
+    fn kv() {
+        let remote = KvEncoder::from_pairs([(1u32, 10u64), (2, 20), (3, 30), (4, 40)]);
+        let local = KvEncoder::from_pairs([(1u32, 10u64), (2, 21), (3, 30), (5, 50)]);
+
+        let diff = kv_difference(remote, local).unwrap();
+        assert_eq!(diff.added, [(4, 40)]);
+        assert_eq!(diff.removed, [(5, 50)]);
+        assert_eq!(
+            diff.changed,
+            [Changed {
+                key: 2,
+                remote: 20,
+                local: 21
+            }]
+        );
+    }
+}
@@ -3,26 +3,103 @@
 #[cfg_attr(test, macro_use)]
 extern crate alloc;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "std"))]
 extern crate std;
 
 mod binaryheap;
+mod cache;
+#[cfg(feature = "tokio")]
+mod codec;
 mod decoder;
 mod encoder;
+mod estimator;
+mod hasher;
 mod index;
+mod indexed;
+mod item;
+mod kv;
+mod multi;
+mod multiset;
+#[cfg(feature = "tokio")]
+mod reconcile;
+mod session;
+mod strategy;
+#[cfg(feature = "futures")]
+mod stream;
+mod subtract;
 mod symbol;
+#[cfg(feature = "std")]
+pub mod wire;
 
-pub use decoder::{set_difference, Decoder};
+pub use cache::{SymbolCache, SymbolCacheIter};
+#[cfg(feature = "tokio")]
+pub use codec::SymbolCodec;
+pub use decoder::{set_difference, DecodeError, Decoder};
 pub use encoder::{Encoder, EncoderIter};
-pub use symbol::Symbol;
+pub use estimator::Estimator;
+#[cfg(feature = "siphash")]
+pub use hasher::SipHash;
+#[cfg(feature = "xxh3")]
+pub use hasher::Xxh3;
+pub use hasher::{Blake3, SymbolHasher};
+pub use indexed::{Indexed, IndexedSymbol};
+pub use item::{Item, VarBytes, VarDecoder, VarEncoder, VarSymbol};
+pub use kv::{kv_difference, Changed, KeyValue, KvDecoder, KvDifference, KvEncoder};
+pub use multi::MultiDecoder;
+pub use multiset::{multiset_difference, Counted};
+#[cfg(feature = "tokio")]
+pub use reconcile::reconcile;
+pub use session::{Message, Outcome, Session, SessionError, Transport};
+pub use strategy::{ReconcileStrategy, Request};
+#[cfg(feature = "futures")]
+pub use stream::{DecoderSink, Difference, SinkError, SymbolStream};
+pub use subtract::subtract;
+pub use symbol::{ChecksumWidth, Symbol};
 use zerocopy::{FromBytes, Immutable, IntoBytes};
 
-fn hash(x: &[u8]) -> [u8; 16] {
-    blake3::hash(x).as_bytes()[..16].try_into().unwrap()
+/// A secret key for BLAKE3 keyed hashing.
+///
+/// Without a key, a malicious peer can craft entries that collide in checksum
+/// or all map to the same cells, stalling decoding.
+pub type Key = [u8; 32];
+
+#[inline]
+fn xor_mut<T: FromBytes + IntoBytes + Immutable + ?Sized>(a: &mut T, b: &T) {
+    xor_bytes(a.as_mut_bytes(), b.as_bytes());
 }
 
-fn xor_mut<T: FromBytes + IntoBytes + Immutable>(a: &mut T, b: &T) {
-    for (a, b) in core::iter::zip(a.as_mut_bytes(), b.as_bytes()) {
+/// XORs `b` into `a`, up to the shorter of the two.
+///
+/// This works a `u128` at a time, which compiles to SIMD registers on targets that
+/// have them, and to pairs of `u64` otherwise. The tail is XORed a `u64` at a time,
+/// and then byte by byte. It is inlined so that fixed size items compile to a few
+/// straight-line instructions.
+///
+/// This is only clearly faster than a byte loop for large items, around 256 bytes
+/// and up. For items of 32 bytes or less, the difference is within noise, because
+/// the compiler already vectorises the byte loop. See `benches/xor.rs`.
+#[inline]
+fn xor_bytes(a: &mut [u8], b: &[u8]) {
+    let n = a.len().min(b.len());
+    let (a, b) = (&mut a[..n], &b[..n]);
+
+    let mut a = a.chunks_exact_mut(16);
+    let mut b = b.chunks_exact(16);
+    for (a, b) in core::iter::zip(&mut a, &mut b) {
+        let x = u128::from_ne_bytes(a.try_into().unwrap());
+        let y = u128::from_ne_bytes(b.try_into().unwrap());
+        a.copy_from_slice(&(x ^ y).to_ne_bytes());
+    }
+
+    let mut a = a.into_remainder().chunks_exact_mut(8);
+    let mut b = b.remainder().chunks_exact(8);
+    for (a, b) in core::iter::zip(&mut a, &mut b) {
+        let x = u64::from_ne_bytes(a.try_into().unwrap());
+        let y = u64::from_ne_bytes(b.try_into().unwrap());
+        a.copy_from_slice(&(x ^ y).to_ne_bytes());
+    }
+
+    for (a, b) in core::iter::zip(a.into_remainder(), b.remainder()) {
         *a ^= *b;
     }
 }
@@ -34,7 +111,51 @@ mod tests {
     use rand_core::{RngCore, SeedableRng};
     use rand_xoshiro::Xoshiro256StarStar;
 
-    use crate::{set_difference, Encoder};
+    use crate::{set_difference, Blake3, DecodeError, Decoder, Encoder, Item, SymbolHasher};
+
+    /// Sorts decoded entries, which come out in no particular order.
+    pub(crate) fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+        items.sort_unstable();
+        items
+    }
+
+    /// Pushes `symbols` into `decoder` until decoding is complete, returning the
+    /// sorted remote and local entries.
+    pub(crate) fn decode<T: Item + Ord, H: SymbolHasher, const W: usize, S>(
+        mut decoder: Decoder<T, H, W>,
+        symbols: impl IntoIterator<Item = S>,
+        mut push: impl FnMut(&mut Decoder<T, H, W>, S) -> Result<(), DecodeError>,
+    ) -> (Vec<T>, Vec<T>) {
+        for symbol in symbols {
+            push(&mut decoder, symbol).unwrap();
+            if decoder.is_complete() {
+                let (remote, local) = decoder.consume();
+                return (sorted(remote), sorted(local));
+            }
+        }
+        panic!("symbols ended before decoding was complete");
+    }
+
+    /// Decodes the difference between the `remote` and `local` sets, encoded with
+    /// `hasher` and `W` checksum bytes.
+    pub(crate) fn difference<H: SymbolHasher, const W: usize>(
+        hasher: H,
+        remote: impl IntoIterator<Item = u64>,
+        local: impl IntoIterator<Item = u64>,
+    ) -> (Vec<u64>, Vec<u64>) {
+        let mut remote_set = Encoder::<u64, H, W>::with_hasher(hasher.clone());
+        remote_set.extend(remote);
+        let mut local_set = Encoder::<u64, H, W>::with_hasher(hasher.clone());
+        local_set.extend(local);
+
+        let decoder = Decoder::with_hasher(hasher);
+        decoder.check_key_id(remote_set.key_id()).unwrap();
+        decode(
+            decoder,
+            core::iter::zip(remote_set, local_set),
+            |d, (r, l)| d.try_push(r, l),
+        )
+    }
 
     #[test]
     fn works() {
@@ -75,6 +196,27 @@ mod tests {
         assert_eq!(local, vec![5]);
     }
 
+    #[test]
+    fn works_keyed() {
+        assert_eq!(
+            difference::<_, 16>(Blake3::with_key([1; 32]), [1, 2, 3, 4], [1, 2, 3, 5]),
+            (vec![4], vec![5])
+        );
+    }
+
+    #[test]
+    fn key_mismatch() {
+        let decoder = Decoder::<u64>::with_key([1; 32]);
+        assert_eq!(
+            decoder.check_key_id(Encoder::<u64>::with_key([2; 32]).key_id()),
+            Err(DecodeError::KeyMismatch)
+        );
+        assert_eq!(
+            decoder.check_key_id(Encoder::<u64>::default().key_id()),
+            Err(DecodeError::KeyMismatch)
+        );
+    }
+
     #[test]
     #[ignore = "very slow"]
     fn huge() {
@@ -126,6 +268,21 @@ mod tests {
         assert_eq!(local.len(), M as usize);
     }
 
+    #[test]
+    fn xor_bytes() {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(0);
+        for len in 0..50 {
+            let mut a = vec![0u8; len];
+            let mut b = vec![0u8; len + 1];
+            rng.fill_bytes(&mut a);
+            rng.fill_bytes(&mut b);
+
+            let expected: Vec<u8> = core::iter::zip(&a, &b).map(|(a, b)| a ^ b).collect();
+            super::xor_bytes(&mut a, &b);
+            assert_eq!(a, expected);
+        }
+    }
+
     #[test]
     fn proptest() {
         arbtest::arbtest(|u| {
@@ -0,0 +1,181 @@
+use alloc::vec::Vec;
+
+use crate::{
+    Blake3, DecodeError, Decoder, Item, Symbol, SymbolCache, SymbolCacheIter, SymbolHasher,
+};
+
+/// Decodes the differences with many remote peers at once.
+///
+/// All peers are decoded against the same local symbols, which are read from a
+/// shared [`SymbolCache`] rather than re-encoding the local set for each peer.
+///
+/// When decoding with one peer completes, the entries it had that the local set
+/// was missing are guessed to be in the other peers' sets too, as is common when
+/// gossiping. A correct guess saves symbols, and a wrong one is undone while decoding.
+pub struct MultiDecoder<T, H = Blake3, const W: usize = 16> {
+    cache: SymbolCache<T, H, W>,
+    peers: Vec<Peer<T, H, W>>,
+}
+
+struct Peer<T, H, const W: usize> {
+    decoder: Decoder<T, H, W>,
+    local: SymbolCacheIter<T, H, W>,
+    /// An error found while guessing entries, reported on the next push.
+    error: Option<DecodeError>,
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> MultiDecoder<T, H, W> {
+    /// Creates a decoder for the local set whose symbols are in `cache`.
+    pub fn new(cache: SymbolCache<T, H, W>) -> Self {
+        Self {
+            cache,
+            peers: Vec::new(),
+        }
+    }
+
+    /// Adds a remote peer, returning its id.
+    pub fn add_peer(&mut self) -> usize {
+        self.peers.push(Peer {
+            decoder: Decoder::with_hasher(self.cache.hasher()),
+            local: self.cache.iter(),
+            error: None,
+        });
+        self.peers.len() - 1
+    }
+
+    /// The number of peers that were added.
+    pub fn peers(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Pushes the next remote symbol from `peer` into its decoder.
+    ///
+    /// Symbols pushed after decoding is complete are ignored. After an error is
+    /// returned, the peer should be discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `peer` was not returned by [`MultiDecoder::add_peer`].
+    pub fn push(&mut self, peer: usize, remote: Symbol<T, W>) -> Result<(), DecodeError> {
+        let p = &mut self.peers[peer];
+        if let Some(e) = p.error {
+            return Err(e);
+        }
+        if p.decoder.is_complete() {
+            return Ok(());
+        }
+
+        let local = p.local.next().unwrap();
+        p.decoder.try_push(remote, local)?;
+        if !p.decoder.is_complete() {
+            return Ok(());
+        }
+
+        let found: Vec<T> = p.decoder.entries().0.iter().map(Item::copy).collect();
+        for (i, other) in self.peers.iter_mut().enumerate() {
+            if i == peer || other.error.is_some() || other.decoder.is_complete() {
+                continue;
+            }
+            for value in &found {
+                if let Err(e) = other.decoder.guess_remote(value) {
+                    other.error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether decoding with `peer` is complete.
+    pub fn is_complete(&self, peer: usize) -> bool {
+        self.peers[peer].decoder.is_complete()
+    }
+
+    /// The entries only `peer` has, and the entries only the local set has,
+    /// once decoding with `peer` is complete.
+    pub fn difference(&self, peer: usize) -> Option<(&[T], &[T])> {
+        let decoder = &self.peers[peer].decoder;
+        decoder.is_complete().then(|| decoder.entries())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::MultiDecoder;
+    use crate::{tests::sorted, Encoder, SymbolCache};
+
+    /// Pushes symbols from `remote` until decoding with `peer` is complete,
+    /// returning the number of symbols used.
+    fn decode(decoder: &mut MultiDecoder<u64>, peer: usize, remote: Vec<u64>) -> usize {
+        let mut n = 0;
+        for symbol in Encoder::from_iter(remote) {
+            decoder.push(peer, symbol).unwrap();
+            n += 1;
+            if decoder.is_complete(peer) {
+                return n;
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn shares_found_items() {
+        let local = Encoder::from_iter(0..1000u64).into_iter();
+        let mut decoder = MultiDecoder::new(SymbolCache::new(local, 64));
+        let a = decoder.add_peer();
+        let b = decoder.add_peer();
+        let c = decoder.add_peer();
+
+        decode(&mut decoder, a, (5..1000).chain(2000..2020).collect());
+        let (remote, local) = decoder.difference(a).unwrap();
+        assert_eq!(sorted(remote.to_vec()), Vec::from_iter(2000..2020));
+        assert_eq!(sorted(local.to_vec()), Vec::from_iter(0..5));
+
+        // b has the same new items, so only its one extra item needs to be decoded.
+        let used = decode(
+            &mut decoder,
+            b,
+            (0..1000).chain(2000..2020).chain([3000]).collect(),
+        );
+        assert!(used < 10, "{used}");
+        let (remote, local) = decoder.difference(b).unwrap();
+        assert_eq!(
+            sorted(remote.to_vec()),
+            [Vec::from_iter(2000..2020), vec![3000]].concat()
+        );
+        assert!(local.is_empty());
+
+        // c only has some of them, so the other guesses are wrong.
+        decode(&mut decoder, c, (0..1000).chain(2000..2010).collect());
+        let (remote, local) = decoder.difference(c).unwrap();
+        assert_eq!(sorted(remote.to_vec()), Vec::from_iter(2000..2010));
+        assert!(local.is_empty());
+    }
+
+    #[cfg(feature = "xxh3")]
+    #[test]
+    fn other_hasher() {
+        use crate::Xxh3;
+
+        let hasher = Xxh3::with_seed(7);
+        let mut local = Encoder::<u64, Xxh3>::with_hasher(hasher.clone());
+        local.extend(0..100u64);
+        let mut decoder = MultiDecoder::new(SymbolCache::new(local.into_iter(), 16));
+        let peer = decoder.add_peer();
+
+        let mut remote = Encoder::<u64, Xxh3>::with_hasher(hasher);
+        remote.extend(5..105u64);
+        for symbol in remote {
+            decoder.push(peer, symbol).unwrap();
+            if decoder.is_complete(peer) {
+                break;
+            }
+        }
+        let (remote, local) = decoder.difference(peer).unwrap();
+        assert_eq!(sorted(remote.to_vec()), Vec::from_iter(100..105));
+        assert_eq!(sorted(local.to_vec()), Vec::from_iter(0..5));
+    }
+}
@@ -0,0 +1,123 @@
+use alloc::vec::Vec;
+
+use zerocopy::{little_endian, FromBytes, Immutable, IntoBytes};
+
+use crate::{decoder::decode_with, kv::join, Decoder, Encoder};
+
+/// An item of a multiset, together with its multiplicity.
+///
+/// The multiplicity is encoded alongside the item, so an item whose multiplicity
+/// differs between the peers is decoded once from each side. XOR sums cannot
+/// represent even multiplicities, so this keeps every cell count at ±1 as usual.
+#[derive(Clone, Copy, FromBytes, Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Counted<T> {
+    item: T,
+    count: little_endian::U64,
+}
+
+impl<T: FromBytes + IntoBytes + Immutable> Counted<T> {
+    /// Pairs `item` with its multiplicity.
+    pub fn new(item: T, count: u64) -> Self {
+        Self {
+            item,
+            count: little_endian::U64::new(count),
+        }
+    }
+
+    /// The item.
+    pub fn item(&self) -> T {
+        T::read_from_prefix(self.as_bytes()).unwrap().0
+    }
+
+    /// The multiplicity of the item.
+    pub fn count(&self) -> u64 {
+        self.count.get()
+    }
+
+    fn item_bytes(&self) -> &[u8] {
+        &self.as_bytes()[..core::mem::size_of::<T>()]
+    }
+}
+
+impl<T: FromBytes + IntoBytes + Immutable> Encoder<Counted<T>> {
+    /// Creates an encoder of a multiset, from items with their multiplicities.
+    ///
+    /// Multiplicities of repeated items are added together, saturating at `u64::MAX`.
+    pub fn from_multiset(iter: impl IntoIterator<Item = (T, u64)>) -> Self {
+        let mut entries: Vec<_> = iter
+            .into_iter()
+            .map(|(item, count)| Counted::new(item, count))
+            .collect();
+        entries.sort_unstable_by(|a, b| Ord::cmp(a.item_bytes(), b.item_bytes()));
+        entries.dedup_by(|a, b| {
+            if a.item_bytes() != b.item_bytes() {
+                return false;
+            }
+            b.count.set(b.count().saturating_add(a.count()));
+            true
+        });
+        entries.retain(|e| e.count() != 0);
+
+        Encoder::from_iter(entries)
+    }
+}
+
+impl<T: FromBytes + IntoBytes + Immutable> Decoder<Counted<T>> {
+    /// Consumes the decoder, returning each item whose multiplicity differs
+    /// along with `remote_count - local_count`, saturating at the bounds of `i64`.
+    pub fn consume_multiset(self) -> Vec<(T, i64)> {
+        let (remote, local) = self.consume();
+        let (remote, local, both) = join(remote, local, Counted::item_bytes);
+
+        let remote = remote.iter().map(|r| (r.item(), difference(r.count(), 0)));
+        let local = local.iter().map(|l| (l.item(), difference(0, l.count())));
+        let both = both
+            .iter()
+            .map(|(r, l)| (r.item(), difference(r.count(), l.count())));
+
+        remote.chain(local).chain(both).collect()
+    }
+}
+
+/// `remote - local`, saturating at the bounds of `i64`.
+fn difference(remote: u64, local: u64) -> i64 {
+    let d = i128::from(remote) - i128::from(local);
+    i64::try_from(d).unwrap_or(if d < 0 { i64::MIN } else { i64::MAX })
+}
+
+/// Decodes the difference in multiplicities between the `remote` and `local` multisets.
+///
+/// Returns `None` if either stream ends before decoding is complete,
+/// or if the streams are found to be corrupted.
+pub fn multiset_difference<T: FromBytes + IntoBytes + Immutable>(
+    remote: impl IntoIterator<Item = crate::Symbol<Counted<T>>>,
+    local: impl IntoIterator<Item = crate::Symbol<Counted<T>>>,
+) -> Option<Vec<(T, i64)>> {
+    decode_with(remote, local, Decoder::consume_multiset)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{multiset_difference, tests::sorted, Encoder};
+
+    #[test]
+    fn multiset() {
+        let remote = Encoder::from_multiset([(1u32, 1), (2, 2), (3, 3), (4, 1), (4, 1)]);
+        let local = Encoder::from_multiset([(1u32, 1), (2, 4), (3, 3), (5, 2), (6, 0)]);
+
+        let diff = sorted(multiset_difference(remote, local).unwrap());
+
+        assert_eq!(diff, [(2, -2), (4, 2), (5, -2)]);
+    }
+
+    #[test]
+    fn saturating_counts() {
+        let remote = Encoder::from_multiset([(1u32, u64::MAX), (1, 1), (2, u64::MAX), (3, 1)]);
+        let local = Encoder::from_multiset([(1u32, u64::MAX), (2, 0), (3, u64::MAX)]);
+
+        let diff = sorted(multiset_difference(remote, local).unwrap());
+
+        assert_eq!(diff, [(2, i64::MAX), (3, i64::MIN)]);
+    }
+}
@@ -0,0 +1,310 @@
+//! Reconciliation over an async byte stream.
+//!
+//! Both peers run [`reconcile`] at the same time. Each peer first writes the
+//! [`Header`] of its symbols, then streams its symbols in frames:
+//!
+//! | frame  | bytes                             | description                           |
+//! |--------|-----------------------------------|---------------------------------------|
+//! | symbol | `0`, then a [symbol](crate::wire) | the next symbol of the sender         |
+//! | done   | `1`                               | the sender has decoded the difference |
+//! | end    | `2`                               | the sender will send no more symbols  |
+//!
+//! A peer sends `done` as soon as its decoder is complete, and stops streaming
+//! symbols with `end` once it has received `done`. Symbols that were already
+//! in flight are discarded. Both `done` and `end` are sent exactly once, so the
+//! stream is fully consumed when reconciliation finishes.
+
+use alloc::vec::Vec;
+use core::{
+    future::poll_fn,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+
+use crate::{
+    wire::{decode_symbol, encode_symbol, Header},
+    Decoder, EncoderIter, Symbol, SymbolHasher,
+};
+
+const SYMBOL: u8 = 0;
+const DONE: u8 = 1;
+const END: u8 = 2;
+
+/// The number of symbols sent before yielding to the executor.
+const BUDGET: usize = 64;
+
+/// Reconciles the `local` set with a peer that is also running `reconcile`.
+///
+/// Symbols are streamed to the peer through `writer` while the peer's symbols are
+/// decoded from `reader`, until both peers have decoded the difference. Returns the
+/// items only the peer has, and the items only the local set has.
+///
+/// The peer must use the same key and item type.
+pub async fn reconcile<T, H, R, O, const W: usize>(
+    mut reader: R,
+    mut writer: O,
+    local: EncoderIter<T, H, W>,
+) -> io::Result<(Vec<T>, Vec<T>)>
+where
+    T: FromBytes + IntoBytes + Immutable,
+    H: SymbolHasher,
+    R: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    let decoder = Decoder::with_hasher(local.hasher.clone());
+
+    let header = Header::new(&local);
+    writer.write_all(header.as_bytes()).await?;
+    writer.flush().await?;
+
+    let mut remote = Header::new_zeroed();
+    reader.read_exact(remote.as_mut_bytes()).await?;
+    remote.check(&decoder)?;
+
+    let mut session = Session {
+        reader: FramedRead::new(reader, FrameCodec::new(&remote)),
+        writer: FramedWrite::new(writer, FrameCodec::new(&header)),
+        decoder,
+        difference: None,
+        local: local.clone(),
+        symbols: local,
+        sent_done: false,
+        sent_end: false,
+        received_done: false,
+        received_end: false,
+    };
+    poll_fn(|cx| session.poll(cx)).await
+}
+
+struct Session<R, O, T, H, const W: usize> {
+    reader: FramedRead<R, FrameCodec<T, W>>,
+    writer: FramedWrite<O, FrameCodec<T, W>>,
+    decoder: Decoder<T, H, W>,
+    difference: Option<(Vec<T>, Vec<T>)>,
+    /// The local symbols that the remote symbols are decoded against.
+    local: EncoderIter<T, H, W>,
+    /// The local symbols that are sent to the peer.
+    symbols: EncoderIter<T, H, W>,
+    sent_done: bool,
+    sent_end: bool,
+    received_done: bool,
+    received_end: bool,
+}
+
+impl<R, O, T, H, const W: usize> Session<R, O, T, H, W>
+where
+    T: FromBytes + IntoBytes + Immutable,
+    H: SymbolHasher,
+    R: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<(Vec<T>, Vec<T>)>> {
+        while !(self.received_done && self.received_end) {
+            match Pin::new(&mut self.reader).poll_next(cx) {
+                Poll::Ready(Some(frame)) => self.receive(frame?)?,
+                Poll::Ready(None) => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+                Poll::Pending => break,
+            }
+        }
+
+        let mut budget = BUDGET;
+        loop {
+            let tag = if self.difference.is_some() && !self.sent_done {
+                DONE
+            } else if self.received_done && !self.sent_end {
+                END
+            } else if !self.received_done && budget > 0 {
+                budget -= 1;
+                SYMBOL
+            } else {
+                break;
+            };
+
+            if Pin::new(&mut self.writer).poll_ready(cx)?.is_pending() {
+                break;
+            }
+            let frame = match tag {
+                DONE => {
+                    self.sent_done = true;
+                    Frame::Done
+                }
+                END => {
+                    self.sent_end = true;
+                    Frame::End
+                }
+                _ => Frame::Symbol(self.symbols.must_next()),
+            };
+            Pin::new(&mut self.writer).start_send(frame)?;
+        }
+        if budget == 0 {
+            cx.waker().wake_by_ref();
+        }
+
+        let flushed = Pin::new(&mut self.writer).poll_flush(cx)?.is_ready();
+        if flushed && self.sent_done && self.sent_end && self.received_done && self.received_end {
+            return Poll::Ready(Ok(self.difference.take().unwrap()));
+        }
+        Poll::Pending
+    }
+
+    fn receive(&mut self, frame: Frame<T, W>) -> io::Result<()> {
+        match frame {
+            Frame::Symbol(_) if self.received_end => {
+                return Err(invalid_data("symbol after end of symbols"));
+            }
+            Frame::Symbol(remote) => {
+                if self.difference.is_some() {
+                    // sent before the peer received our done frame.
+                    return Ok(());
+                }
+                self.decoder
+                    .try_push(remote, self.local.must_next())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                if self.decoder.is_complete() {
+                    let empty = Decoder::with_hasher(self.decoder.hasher.clone());
+                    let decoder = core::mem::replace(&mut self.decoder, empty);
+                    self.difference = Some(decoder.consume());
+                }
+            }
+            Frame::Done => self.received_done = true,
+            Frame::End if !self.sent_done => {
+                return Err(invalid_data("end of symbols before decoding completed"));
+            }
+            Frame::End => self.received_end = true,
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+enum Frame<T, const W: usize> {
+    Symbol(Symbol<T, W>),
+    Done,
+    End,
+}
+
+struct FrameCodec<T, const W: usize> {
+    header: Header,
+    _item: PhantomData<T>,
+}
+
+impl<T, const W: usize> FrameCodec<T, W> {
+    fn new(header: &Header) -> Self {
+        Self {
+            header: *header,
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T: FromBytes + IntoBytes + Immutable, const W: usize> tokio_util::codec::Decoder
+    for FrameCodec<T, W>
+{
+    type Item = Frame<T, W>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(&tag) = src.first() else {
+            return Ok(None);
+        };
+        let frame = match tag {
+            SYMBOL => match decode_symbol(&self.header, &src[1..])? {
+                Some((symbol, len)) => {
+                    src.advance(len);
+                    Frame::Symbol(symbol)
+                }
+                None => return Ok(None),
+            },
+            DONE => Frame::Done,
+            END => Frame::End,
+            _ => return Err(invalid_data("unknown frame")),
+        };
+        src.advance(1);
+        Ok(Some(frame))
+    }
+}
+
+impl<T: IntoBytes + Immutable, const W: usize> tokio_util::codec::Encoder<Frame<T, W>>
+    for FrameCodec<T, W>
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Frame<T, W>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match item {
+            Frame::Symbol(symbol) => {
+                dst.put_u8(SYMBOL);
+                encode_symbol(&self.header, &symbol, |b| {
+                    dst.extend_from_slice(b);
+                    Ok(())
+                })
+            }
+            Frame::Done => {
+                dst.put_u8(DONE);
+                Ok(())
+            }
+            Frame::End => {
+                dst.put_u8(END);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::reconcile;
+    use crate::{tests::sorted, Encoder};
+
+    #[tokio::test]
+    async fn both_ends() {
+        let alice = Encoder::from_iter(0..1000u64).into_iter();
+        let bob = Encoder::from_iter(10..1010u64).into_iter();
+
+        let (a, b) = tokio::io::duplex(64);
+        let (a_read, a_write) = tokio::io::split(a);
+        let (b_read, b_write) = tokio::io::split(b);
+
+        let (alice, bob) = tokio::join!(
+            reconcile(a_read, a_write, alice),
+            reconcile(b_read, b_write, bob)
+        );
+
+        let (bob_only, alice_only) = alice.unwrap();
+        assert_eq!(sorted(bob_only), Vec::from_iter(1000..1010));
+        assert_eq!(sorted(alice_only), Vec::from_iter(0..10));
+
+        let (alice_only, bob_only) = bob.unwrap();
+        assert_eq!(sorted(bob_only), Vec::from_iter(1000..1010));
+        assert_eq!(sorted(alice_only), Vec::from_iter(0..10));
+    }
+
+    #[tokio::test]
+    async fn key_mismatch() {
+        let alice = Encoder::from_iter(0..10u64).into_iter();
+        let bob = Encoder::<u64>::with_key([7; 32]);
+
+        let (a, b) = tokio::io::duplex(64);
+        let (a_read, a_write) = tokio::io::split(a);
+        let (b_read, b_write) = tokio::io::split(b);
+
+        let (alice, _) = tokio::join!(
+            reconcile(a_read, a_write, alice),
+            reconcile(b_read, b_write, bob.into_iter())
+        );
+        assert!(alice.is_err());
+    }
+}
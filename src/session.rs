@@ -0,0 +1,265 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Blake3, DecodeError, Decoder, EncoderIter, Item, Symbol, SymbolHasher};
+
+/// A message exchanged by a [`Session`].
+pub enum Message<T, const W: usize = 16> {
+    /// The next batch of symbols from the initiator.
+    Symbols(Vec<Symbol<T, W>>),
+    /// The responder needs more symbols.
+    More,
+    /// The responder has decoded the difference.
+    Done {
+        /// The items the initiator is missing.
+        missing: Vec<T>,
+        /// The items the responder was missing, if it reports them.
+        found: Option<Vec<T>>,
+    },
+}
+
+/// Carries [`Message`]s between the two peers of a [`Session`].
+pub trait Transport<T, const W: usize = 16> {
+    type Error;
+
+    /// Sends `message` to the peer.
+    fn send(&mut self, message: Message<T, W>) -> Result<(), Self::Error>;
+
+    /// Receives the next message from the peer.
+    fn recv(&mut self) -> Result<Message<T, W>, Self::Error>;
+}
+
+/// What a peer learned from a [`Session`].
+///
+/// Adding the `received` items to the local set gives the union of both sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome<T> {
+    /// The items that were missing from the local set.
+    pub received: Vec<T>,
+    /// The items that were missing from the peer's set.
+    ///
+    /// This is `None` for the initiator if the responder does not report them.
+    pub sent: Option<Vec<T>>,
+}
+
+/// An error returned by a [`Session`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionError<E> {
+    /// The transport failed.
+    Transport(E),
+    /// The initiator's symbols are corrupted.
+    Decode(DecodeError),
+    /// The peer sent a message that is not valid at this point.
+    UnexpectedMessage,
+}
+
+impl<E: fmt::Display> fmt::Display for SessionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Transport(e) => write!(f, "transport error: {e}"),
+            SessionError::Decode(e) => e.fmt(f),
+            SessionError::UnexpectedMessage => f.write_str("unexpected message from peer"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> core::error::Error for SessionError<E> {}
+
+/// A reconciliation where both peers learn the difference.
+///
+/// The initiator streams symbols in batches, and the responder decodes them
+/// against its own symbols. Once decoding is complete, the responder sends back
+/// the items the initiator is missing, so both peers converge to the union.
+///
+/// Both peers must use the same key.
+pub struct Session<T, H = Blake3, const W: usize = 16> {
+    local: EncoderIter<T, H, W>,
+    batch_size: usize,
+    report_found: bool,
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Session<T, H, W> {
+    /// Creates a session for the `local` set.
+    pub fn new(local: EncoderIter<T, H, W>) -> Self {
+        Self {
+            local,
+            batch_size: 64,
+            report_found: false,
+        }
+    }
+
+    /// Sets the number of symbols the initiator sends before waiting for a reply.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.max(1);
+    }
+
+    /// Sets whether the responder also sends the items it was missing,
+    /// so the initiator learns the whole difference.
+    pub fn set_report_found(&mut self, report_found: bool) {
+        self.report_found = report_found;
+    }
+
+    /// Runs the session as the initiator, which streams its symbols to the responder.
+    pub fn initiate<X: Transport<T, W>>(
+        mut self,
+        transport: &mut X,
+    ) -> Result<Outcome<T>, SessionError<X::Error>> {
+        loop {
+            let symbols = self.local.by_ref().take(self.batch_size).collect();
+            transport
+                .send(Message::Symbols(symbols))
+                .map_err(SessionError::Transport)?;
+
+            match transport.recv().map_err(SessionError::Transport)? {
+                Message::More => {}
+                Message::Done { missing, found } => {
+                    return Ok(Outcome {
+                        received: missing,
+                        sent: found,
+                    })
+                }
+                Message::Symbols(_) => return Err(SessionError::UnexpectedMessage),
+            }
+        }
+    }
+
+    /// Runs the session as the responder, which decodes the initiator's symbols.
+    pub fn respond<X: Transport<T, W>>(
+        mut self,
+        transport: &mut X,
+    ) -> Result<Outcome<T>, SessionError<X::Error>> {
+        let mut decoder = Decoder::with_hasher(self.local.hasher.clone());
+
+        loop {
+            let Message::Symbols(symbols) = transport.recv().map_err(SessionError::Transport)?
+            else {
+                return Err(SessionError::UnexpectedMessage);
+            };
+
+            for remote in symbols {
+                decoder
+                    .try_push(remote, self.local.must_next())
+                    .map_err(SessionError::Decode)?;
+                if decoder.is_complete() {
+                    break;
+                }
+            }
+
+            if decoder.is_complete() {
+                let (remote, local) = decoder.consume();
+                let message = Message::Done {
+                    missing: local.iter().map(Item::copy).collect(),
+                    found: self
+                        .report_found
+                        .then(|| remote.iter().map(Item::copy).collect()),
+                };
+                transport.send(message).map_err(SessionError::Transport)?;
+
+                return Ok(Outcome {
+                    received: remote,
+                    sent: Some(local),
+                });
+            }
+
+            transport
+                .send(Message::More)
+                .map_err(SessionError::Transport)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{channel, Receiver, RecvError, Sender},
+        thread,
+        vec::Vec,
+    };
+
+    use super::{Message, Outcome, Session, Transport};
+    use crate::{tests::sorted, Blake3, Encoder};
+
+    struct Channel<T, const W: usize> {
+        tx: Sender<Message<T, W>>,
+        rx: Receiver<Message<T, W>>,
+    }
+
+    impl<T, const W: usize> Transport<T, W> for Channel<T, W> {
+        type Error = RecvError;
+
+        fn send(&mut self, message: Message<T, W>) -> Result<(), Self::Error> {
+            self.tx.send(message).map_err(|_| RecvError)
+        }
+
+        fn recv(&mut self) -> Result<Message<T, W>, Self::Error> {
+            self.rx.recv()
+        }
+    }
+
+    fn pair<T, const W: usize>() -> (Channel<T, W>, Channel<T, W>) {
+        let (a_tx, b_rx) = channel();
+        let (b_tx, a_rx) = channel();
+        (
+            Channel { tx: a_tx, rx: a_rx },
+            Channel { tx: b_tx, rx: b_rx },
+        )
+    }
+
+    #[test]
+    fn both_peers_learn_difference() {
+        let (mut a, mut b) = pair();
+
+        let alice = Session::new(Encoder::from_iter(0..1000u64).into_iter());
+        let mut bob = Session::new(Encoder::from_iter(10..1010u64).into_iter());
+        bob.set_report_found(true);
+
+        let alice = thread::spawn(move || alice.initiate(&mut a).unwrap());
+        let bob = bob.respond(&mut b).unwrap();
+        let alice = alice.join().unwrap();
+
+        assert_eq!(sorted(alice.received), Vec::from_iter(1000..1010));
+        assert_eq!(sorted(alice.sent.unwrap()), Vec::from_iter(0..10));
+        assert_eq!(sorted(bob.received), Vec::from_iter(0..10));
+        assert_eq!(sorted(bob.sent.unwrap()), Vec::from_iter(1000..1010));
+    }
+
+    #[test]
+    fn without_report() {
+        let (mut a, mut b) = pair();
+
+        let alice = Session::new(Encoder::from_iter([1u64, 2, 3, 4]).into_iter());
+        let bob = Session::new(Encoder::from_iter([1u64, 2, 3, 5]).into_iter());
+
+        let alice = thread::spawn(move || alice.initiate(&mut a).unwrap());
+        bob.respond(&mut b).unwrap();
+
+        assert_eq!(
+            alice.join().unwrap(),
+            Outcome {
+                received: vec![5],
+                sent: None
+            }
+        );
+    }
+
+    #[test]
+    fn narrow_checksums() {
+        let (mut a, mut b) = pair();
+
+        let mut alice = Encoder::<u64, Blake3, 8>::with_hasher(Blake3::default());
+        alice.extend(0..100);
+        let mut bob = Encoder::<u64, Blake3, 8>::with_hasher(Blake3::default());
+        bob.extend(5..105);
+        let alice = Session::new(alice.into_iter());
+        let bob = Session::new(bob.into_iter());
+
+        let alice = thread::spawn(move || alice.initiate(&mut a).unwrap());
+        let bob = bob.respond(&mut b).unwrap();
+
+        assert_eq!(
+            sorted(alice.join().unwrap().received),
+            Vec::from_iter(100..105)
+        );
+        assert_eq!(sorted(bob.received), Vec::from_iter(0..5));
+    }
+}
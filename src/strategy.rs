@@ -0,0 +1,280 @@
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use crate::{kv::join, Blake3, DecodeError, Decoder, EncoderIter, Item, Symbol, SymbolHasher};
+
+/// What a [`ReconcileStrategy`] needs from the remote peer next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Keep sending coded symbols.
+    More,
+    /// Send the whole set with [`EncoderIter::full_transfer`] instead, to be pushed
+    /// with [`ReconcileStrategy::push_entry`].
+    Full,
+    /// The difference is known, and no more symbols are needed.
+    Done,
+}
+
+/// Decodes the difference with a remote peer, falling back to a transfer of the whole
+/// remote set once that is cheaper.
+///
+/// Decoding a difference of `d` entries takes about `1.35 * d` coded symbols, which can
+/// be more than the size of the remote set when the sets have diverged a lot. Once as
+/// many coded symbols were received as there are entries in the remote set, this requests
+/// a full transfer, so at most about twice the cheaper of the two is sent.
+///
+/// A full transfer sends each entry as a symbol, so it can use the same wire framing,
+/// but the transport must tell the two kinds of symbols apart, e.g. by frame type.
+/// Coded symbols go to [`ReconcileStrategy::push`], and entries to
+/// [`ReconcileStrategy::push_entry`]. Coded symbols that were still in flight when the
+/// full transfer was requested are ignored.
+pub struct ReconcileStrategy<T, H = Blake3, const W: usize = 16> {
+    decoder: Decoder<T, H, W>,
+    local: EncoderIter<T, H, W>,
+    received: u64,
+    remote_size: u64,
+    /// The distinct remote entries, while a full transfer is in progress.
+    full: Option<BTreeSet<ByBytes<T>>>,
+    full_transfer: bool,
+    difference: Option<(Vec<T>, Vec<T>)>,
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> ReconcileStrategy<T, H, W> {
+    /// Creates a strategy that decodes remote symbols with `decoder` against the `local` symbols.
+    pub fn new(decoder: Decoder<T, H, W>, local: EncoderIter<T, H, W>) -> Self {
+        Self {
+            decoder,
+            local,
+            received: 0,
+            remote_size: 0,
+            full: None,
+            full_transfer: false,
+            difference: None,
+        }
+    }
+
+    /// Pushes the next coded remote symbol, returning what is needed next.
+    ///
+    /// Once a full transfer was requested, coded symbols are ignored.
+    /// After an error is returned, the strategy should be discarded.
+    pub fn push(&mut self, remote: Symbol<T, W>) -> Result<Request, DecodeError> {
+        if self.difference.is_some() {
+            return Ok(Request::Done);
+        }
+        if self.full.is_some() {
+            return Ok(Request::Full);
+        }
+
+        if self.received == 0 {
+            self.remote_size = remote.count.get().max(0) as u64;
+        }
+        self.decoder.try_push(remote, self.local.must_next())?;
+        self.received += 1;
+
+        if self.decoder.is_complete() {
+            let empty = Decoder::with_hasher(self.decoder.hasher.clone());
+            let decoder = core::mem::replace(&mut self.decoder, empty);
+            self.difference = Some(decoder.consume());
+            return Ok(Request::Done);
+        }
+
+        if self.received >= self.remote_size {
+            self.full = Some(BTreeSet::new());
+            self.full_transfer = true;
+            return Ok(self.finish_full());
+        }
+
+        Ok(Request::More)
+    }
+
+    /// Pushes the next remote entry of a full transfer, returning what is needed next.
+    ///
+    /// Entries are ignored unless a full transfer was requested, and repeated entries
+    /// are only counted once. After an error is returned, the strategy should be discarded.
+    pub fn push_entry(&mut self, entry: Symbol<T, W>) -> Result<Request, DecodeError> {
+        let Some(entries) = &mut self.full else {
+            return Ok(if self.difference.is_some() {
+                Request::Done
+            } else {
+                Request::More
+            });
+        };
+
+        let hasher = &self.decoder.hasher;
+        if entry.count != 1 || !entry.is_pure_cell(hasher, self.decoder.checksum_width()) {
+            return Err(DecodeError::InvalidEntry {
+                index: entries.len() as u64,
+            });
+        }
+        entries.insert(ByBytes(entry.sum));
+        Ok(self.finish_full())
+    }
+
+    /// Whether a full transfer of the remote set was requested.
+    pub fn is_full_transfer(&self) -> bool {
+        self.full_transfer
+    }
+
+    /// Returns the remote and local entries that are not in the other set,
+    /// if the difference is known.
+    pub fn consume(self) -> Option<(Vec<T>, Vec<T>)> {
+        self.difference
+    }
+
+    /// Computes the difference once all remote entries were received.
+    fn finish_full(&mut self) -> Request {
+        let Some(entries) = &self.full else {
+            return Request::More;
+        };
+        if (entries.len() as u64) < self.remote_size {
+            return Request::Full;
+        }
+
+        let remote = self.full.take().unwrap();
+        let remote = remote.into_iter().map(|entry| entry.0).collect();
+        let local = self.local.entries.iter().map(Item::copy).collect();
+
+        let (remote, local, _) = join(remote, local, T::bytes);
+        self.difference = Some((remote, local));
+        Request::Done
+    }
+}
+
+/// Orders entries by their bytes.
+struct ByBytes<T>(T);
+
+impl<T: Item> PartialEq for ByBytes<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.bytes() == other.0.bytes()
+    }
+}
+
+impl<T: Item> Eq for ByBytes<T> {}
+
+impl<T: Item> PartialOrd for ByBytes<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Item> Ord for ByBytes<T> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        Ord::cmp(self.0.bytes(), other.0.bytes())
+    }
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> EncoderIter<T, H, W> {
+    /// Returns every entry of the set as a symbol, for a full transfer requested by
+    /// a [`ReconcileStrategy`].
+    pub fn full_transfer(&self) -> impl Iterator<Item = Symbol<T, W>> + '_ {
+        self.entries.iter().map(|entry| {
+            let mut symbol = Symbol::<T, W>::default();
+            symbol.add_entry(entry, &self.hasher.hash(entry.bytes()));
+            symbol
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{ReconcileStrategy, Request};
+    use crate::{tests::sorted, DecodeError, Decoder, Encoder};
+
+    fn reconcile(remote: Vec<u64>, local: Vec<u64>) -> (bool, Vec<u64>, Vec<u64>) {
+        let remote = Encoder::from_iter(remote).into_iter();
+        let local = Encoder::from_iter(local).into_iter();
+        let mut strategy = ReconcileStrategy::new(Decoder::default(), local);
+
+        let full = remote.full_transfer().collect::<Vec<_>>();
+        let mut request = Request::More;
+        for symbol in remote {
+            request = strategy.push(symbol).unwrap();
+            if request != Request::More {
+                break;
+            }
+        }
+        if request == Request::Full {
+            for symbol in full {
+                request = strategy.push_entry(symbol).unwrap();
+            }
+        }
+        assert_eq!(request, Request::Done);
+
+        let full = strategy.is_full_transfer();
+        let (remote, local) = strategy.consume().unwrap();
+        (full, sorted(remote), sorted(local))
+    }
+
+    #[test]
+    fn small_difference() {
+        let (full, remote, local) = reconcile((0..100).collect(), (5..105).collect());
+        assert!(!full);
+        assert_eq!(remote, Vec::from_iter(0..5));
+        assert_eq!(local, Vec::from_iter(100..105));
+    }
+
+    #[test]
+    fn falls_back_to_full_transfer() {
+        let (full, remote, local) = reconcile((0..100).collect(), (50..150).collect());
+        assert!(full);
+        assert_eq!(remote, Vec::from_iter(0..50));
+        assert_eq!(local, Vec::from_iter(100..150));
+    }
+
+    #[test]
+    fn invalid_entry() {
+        let remote = Encoder::from_iter(0..10u64).into_iter();
+        let local = Encoder::from_iter(20..30u64).into_iter();
+        let mut strategy = ReconcileStrategy::new(Decoder::default(), local);
+
+        let mut request = Request::More;
+        for symbol in remote.clone() {
+            request = strategy.push(symbol).unwrap();
+            if request != Request::More {
+                break;
+            }
+        }
+        assert_eq!(request, Request::Full);
+
+        let mut symbols = remote.full_transfer();
+        strategy.push_entry(symbols.next().unwrap()).unwrap();
+        let mut corrupt = symbols.next().unwrap();
+        corrupt.checksum[0] ^= 1;
+        assert_eq!(
+            strategy.push_entry(corrupt),
+            Err(DecodeError::InvalidEntry { index: 1 })
+        );
+    }
+
+    #[test]
+    fn stale_and_repeated_symbols() {
+        let remote = Encoder::from_iter(0..10u64).into_iter();
+        let local = Encoder::from_iter(20..30u64).into_iter();
+        let mut strategy = ReconcileStrategy::new(Decoder::default(), local);
+
+        let mut coded = remote.clone();
+        let mut request = Request::More;
+        while request == Request::More {
+            request = strategy.push(coded.next().unwrap()).unwrap();
+        }
+        assert_eq!(request, Request::Full);
+
+        // coded symbols still in flight are ignored.
+        for symbol in coded.by_ref().take(5) {
+            assert_eq!(strategy.push(symbol), Ok(Request::Full));
+        }
+
+        // retransmitted entries do not end the transfer early.
+        let entries: Vec<_> = remote.full_transfer().collect();
+        for symbol in entries.iter().take(9) {
+            assert_eq!(strategy.push_entry(*symbol), Ok(Request::Full));
+            assert_eq!(strategy.push_entry(*symbol), Ok(Request::Full));
+        }
+        assert_eq!(strategy.push_entry(entries[9]), Ok(Request::Done));
+
+        let (remote, local) = strategy.consume().unwrap();
+        assert_eq!(sorted(remote), Vec::from_iter(0..10));
+        assert_eq!(sorted(local), Vec::from_iter(20..30));
+    }
+}
@@ -0,0 +1,194 @@
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use alloc::vec::Vec;
+use futures_channel::oneshot;
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::{Blake3, DecodeError, Decoder, EncoderIter, Item, Symbol, SymbolHasher};
+
+/// The remote and local items, or the error that stopped decoding.
+type Decoded<T> = Result<(Vec<T>, Vec<T>), DecodeError>;
+
+/// An [`EncoderIter`] as a [`Stream`] of symbols.
+pub struct SymbolStream<T, H = Blake3, const W: usize = 16> {
+    inner: EncoderIter<T, H, W>,
+}
+
+impl<T, H, const W: usize> EncoderIter<T, H, W> {
+    /// Converts this iterator into a [`Stream`] of symbols.
+    pub fn into_stream(self) -> SymbolStream<T, H, W> {
+        SymbolStream { inner: self }
+    }
+}
+
+// there is no structural pinning.
+impl<T, H, const W: usize> Unpin for SymbolStream<T, H, W> {}
+impl<T, H, const W: usize> Unpin for DecoderSink<T, H, W> {}
+impl<T> Unpin for Difference<T> {}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Stream for SymbolStream<T, H, W> {
+    type Item = Symbol<T, W>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(Some(self.get_mut().inner.must_next()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+/// A [`Sink`] of remote symbols, which are decoded against a local [`EncoderIter`].
+///
+/// Once decoding is complete, the [`Difference`] future resolves and the sink
+/// rejects any further symbols with [`SinkError::Complete`]. This stops
+/// [`forward`](https://docs.rs/futures/latest/futures/stream/trait.StreamExt.html#method.forward)
+/// from reading more remote symbols than needed, but it also means that a successful
+/// `forward` ends with `Err(SinkError::Complete)`, not `Ok(())`. Symbol streams are
+/// unbounded, so `forward` only returns `Ok(())` if the remote stream ends early.
+///
+/// Treat [`SinkError::Complete`] as success, and take the result from the
+/// [`Difference`]:
+///
+/// ```
+/// # use futures::{executor::block_on, StreamExt};
+/// # use rateless_iblt::{Decoder, DecoderSink, Encoder, SinkError};
+/// # block_on(async {
+/// let remote = Encoder::from_iter([1u64, 2, 3, 4]).into_iter().into_stream();
+/// let local = Encoder::from_iter([1u64, 2, 3, 5]).into_iter();
+///
+/// let (sink, difference) = DecoderSink::new(Decoder::default(), local);
+/// match remote.map(Ok).forward(sink).await {
+///     Ok(()) | Err(SinkError::Complete) => {}
+///     Err(SinkError::Decode(e)) => panic!("corrupted stream: {e}"),
+/// }
+/// let (remote, local) = difference.await.unwrap().unwrap();
+/// assert_eq!((remote, local), (vec![4], vec![5]));
+/// # });
+/// ```
+pub struct DecoderSink<T, H = Blake3, const W: usize = 16> {
+    decoder: Option<Decoder<T, H, W>>,
+    local: EncoderIter<T, H, W>,
+    tx: Option<oneshot::Sender<Decoded<T>>>,
+}
+
+/// Resolves to the difference decoded by a [`DecoderSink`].
+///
+/// Resolves to `None` if the sink was dropped before decoding completed.
+pub struct Difference<T> {
+    rx: oneshot::Receiver<Decoded<T>>,
+}
+
+/// An error returned by a [`DecoderSink`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    /// Decoding is complete, and no more symbols are needed.
+    ///
+    /// This is how a successful `forward` into a [`DecoderSink`] ends.
+    Complete,
+    /// The remote symbols are corrupted.
+    Decode(DecodeError),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Complete => f.write_str("decoding is complete"),
+            SinkError::Decode(e) => e.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for SinkError {}
+
+impl<T, H, const W: usize> DecoderSink<T, H, W> {
+    /// Creates a sink that decodes remote symbols with `decoder` against the `local` symbols.
+    pub fn new(decoder: Decoder<T, H, W>, local: EncoderIter<T, H, W>) -> (Self, Difference<T>) {
+        let (tx, rx) = oneshot::channel();
+        let sink = Self {
+            decoder: Some(decoder),
+            local,
+            tx: Some(tx),
+        };
+        (sink, Difference { rx })
+    }
+
+    fn finish(&mut self, result: Decoded<T>) {
+        self.decoder = None;
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(result);
+        }
+    }
+}
+
+impl<T: Item, H: SymbolHasher, const W: usize> Sink<Symbol<T, W>> for DecoderSink<T, H, W> {
+    type Error = SinkError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.decoder.is_none() {
+            return Poll::Ready(Err(SinkError::Complete));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, remote: Symbol<T, W>) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let decoder = this.decoder.as_mut().ok_or(SinkError::Complete)?;
+
+        if let Err(e) = decoder.try_push(remote, this.local.must_next()) {
+            this.finish(Err(e));
+            return Err(SinkError::Decode(e));
+        }
+
+        if decoder.is_complete() {
+            let decoder = this.decoder.take().unwrap();
+            this.finish(Ok(decoder.consume()));
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Future for Difference<T> {
+    type Output = Option<Decoded<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(Result::ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, StreamExt};
+
+    use super::{DecoderSink, SinkError};
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn forward() {
+        let remote = Encoder::from_iter([1u64, 2, 3, 4]).into_iter();
+        let local = Encoder::from_iter([1u64, 2, 3, 5]).into_iter();
+
+        let (sink, difference) = DecoderSink::new(Decoder::default(), local);
+        let result = block_on(remote.into_stream().map(Ok).forward(sink));
+        assert_eq!(result, Err(SinkError::Complete));
+
+        let (remote, local) = block_on(difference).unwrap().unwrap();
+        assert_eq!(remote, vec![4]);
+        assert_eq!(local, vec![5]);
+    }
+}
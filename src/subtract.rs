@@ -0,0 +1,97 @@
+use crate::{Item, Symbol};
+
+/// Combines the symbol streams of the sets `a` and `b` into the stream of `a - b`,
+/// without decoding either of them.
+///
+/// When `b` is a subset of `a`, this is exactly the stream of `a \ b`, and can be
+/// decoded against another set as usual. Otherwise, it is the signed difference of
+/// the two sets, which can be decoded on its own with
+/// [`Decoder::push_difference`](crate::Decoder::push_difference) to recover both
+/// `a \ b` and `b \ a`.
+///
+/// Both streams must start at the first symbol, and use the same key.
+pub fn subtract<T: Item, const W: usize>(
+    a: impl IntoIterator<Item = Symbol<T, W>>,
+    b: impl IntoIterator<Item = Symbol<T, W>>,
+) -> impl Iterator<Item = Symbol<T, W>> {
+    let mut a = a.into_iter();
+    let mut b = b.into_iter();
+    let mut index = 0;
+    let (mut n_a, mut n_b) = (0, 0);
+
+    core::iter::from_fn(move || {
+        let mut a = a.next()?;
+        let mut b = b.next()?;
+
+        if index == 0 {
+            n_a = a.count.get();
+            n_b = b.count.get();
+            index += 1;
+            return Some(a - b);
+        }
+
+        // counts are encoded relative to the expected count for the size of each set,
+        // which is not linear in the size. so decode both, and encode the difference
+        // relative to the size of the difference.
+        a.decode_count(index, n_a);
+        b.decode_count(index, n_b);
+        let mut s = a - b;
+        s.encode_count(index, n_a.wrapping_sub(n_b));
+
+        index += 1;
+        Some(s)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::subtract;
+    use crate::{set_difference, tests::decode, Blake3, Decoder, Encoder};
+
+    #[test]
+    fn subset() {
+        let a = Encoder::from_iter(0..100u64);
+        let b = Encoder::from_iter(0..90u64);
+
+        let diff: Vec<_> = subtract(a, b).take(50).collect();
+        let expected: Vec<_> = Encoder::from_iter(90..100u64)
+            .into_iter()
+            .take(50)
+            .collect();
+
+        assert_eq!(diff, expected);
+
+        // a third party can decode the forwarded stream against its own set.
+        let (remote, local) = set_difference(diff, Encoder::from_iter(95..101u64)).unwrap();
+        assert_eq!(remote.len(), 5);
+        assert_eq!(local, [100]);
+    }
+
+    #[test]
+    fn symmetric() {
+        let a = Encoder::from_iter(0..100u64);
+        let b = Encoder::from_iter(10..120u64);
+
+        let (a_only, b_only) = decode(Decoder::default(), subtract(a, b), Decoder::push_difference);
+        assert_eq!(a_only, Vec::from_iter(0..10));
+        assert_eq!(b_only, Vec::from_iter(100..120));
+    }
+
+    #[test]
+    fn narrow_checksums() {
+        let mut a = Encoder::<u64, _, 8>::with_hasher(Blake3::default());
+        let mut b = Encoder::<u64, _, 8>::with_hasher(Blake3::default());
+        a.extend(0..100);
+        b.extend(5..100);
+
+        let (a_only, b_only) = decode(
+            Decoder::<u64, _, 8>::with_hasher(Blake3::default()),
+            subtract(a, b),
+            Decoder::push_difference,
+        );
+        assert_eq!(a_only, Vec::from_iter(0..5));
+        assert!(b_only.is_empty());
+    }
+}
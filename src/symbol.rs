@@ -1,29 +1,34 @@
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
-use zerocopy::{little_endian, FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout, Unaligned};
+use zerocopy::{little_endian, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
-use crate::{hash, xor_mut};
+use crate::{xor_mut, Item, SymbolHasher};
 
+/// A coded symbol, holding the sum of the entries mapped to it.
+///
+/// `W` is the number of checksum bytes, which is one of 4, 8, 16 or 32.
+/// See [`ChecksumWidth`] for the trade-offs.
 #[derive(Debug, Clone, Copy, FromBytes, Immutable, IntoBytes, Unaligned, KnownLayout)]
+#[cfg_attr(test, derive(PartialEq))]
 #[repr(C)]
-pub struct Symbol<T> {
+pub struct Symbol<T, const W: usize = 16> {
     pub(crate) sum: T,
-    pub(crate) checksum: [u8; 16],
+    pub(crate) checksum: [u8; W],
     pub(crate) count: little_endian::I64,
 }
 
-impl<T: FromZeros> Default for Symbol<T> {
+impl<T: Item, const W: usize> Default for Symbol<T, W> {
     fn default() -> Self {
         Self {
-            sum: T::new_zeroed(),
-            checksum: Default::default(),
+            sum: T::zero(),
+            checksum: [0; W],
             count: Default::default(),
         }
     }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> Sub for Symbol<T> {
-    type Output = Symbol<T>;
+impl<T: Item, const W: usize> Sub for Symbol<T, W> {
+    type Output = Symbol<T, W>;
 
     fn sub(mut self, rhs: Self) -> Self::Output {
         self -= &rhs;
@@ -31,17 +36,17 @@ impl<T: FromBytes + IntoBytes + Immutable> Sub for Symbol<T> {
     }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> SubAssign<&Symbol<T>> for Symbol<T> {
+impl<T: Item, const W: usize> SubAssign<&Symbol<T, W>> for Symbol<T, W> {
     fn sub_assign(&mut self, rhs: &Self) {
-        xor_mut(&mut self.sum, &rhs.sum);
+        self.sum.xor(&rhs.sum);
         xor_mut(&mut self.checksum, &rhs.checksum);
         self.count
             .set(self.count.get().wrapping_sub(rhs.count.get()));
     }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> Add for Symbol<T> {
-    type Output = Symbol<T>;
+impl<T: Item, const W: usize> Add for Symbol<T, W> {
+    type Output = Symbol<T, W>;
 
     fn add(mut self, rhs: Self) -> Self::Output {
         self += rhs;
@@ -49,50 +54,106 @@ impl<T: FromBytes + IntoBytes + Immutable> Add for Symbol<T> {
     }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> AddAssign for Symbol<T> {
+impl<T: Item, const W: usize> AddAssign for Symbol<T, W> {
     fn add_assign(&mut self, rhs: Self) {
-        xor_mut(&mut self.sum, &rhs.sum);
+        self.sum.xor(&rhs.sum);
         xor_mut(&mut self.checksum, &rhs.checksum);
         self.count
             .set(self.count.get().wrapping_add(rhs.count.get()));
     }
 }
 
-impl<T: FromBytes + IntoBytes + Immutable> Symbol<T> {
-    pub(crate) fn add_entry(&mut self, value: &T, checksum: &[u8; 16]) {
-        xor_mut(&mut self.sum, value);
+impl<T: Item, const W: usize> Symbol<T, W> {
+    pub(crate) fn add_entry(&mut self, value: &T, checksum: &[u8; W]) {
+        self.sum.xor(value);
         xor_mut(&mut self.checksum, checksum);
         self.count.set(self.count.get().wrapping_add(1));
     }
 
     pub(crate) fn copy(&self) -> Self {
         Symbol {
-            sum: T::read_from_bytes(self.sum.as_bytes()).unwrap(),
+            sum: self.sum.copy(),
             checksum: self.checksum,
             count: self.count,
         }
     }
 }
 
-impl<T: IntoBytes + Immutable> Symbol<T> {
-    pub(crate) fn is_pure_cell(&self) -> bool {
-        self.count.get().abs() == 1 && hash(self.sum.as_bytes()) == self.checksum
+impl<T: Item, const W: usize> Symbol<T, W> {
+    pub(crate) fn is_pure_cell<H: SymbolHasher>(&self, hasher: &H, width: ChecksumWidth) -> bool {
+        let w = width.bytes();
+        self.count.get().abs() == 1 && hasher.hash::<W>(self.sum.bytes())[..w] == self.checksum[..w]
     }
 
-    pub(crate) fn is_empty_cell(&self) -> bool {
-        self.count == 0 && self.checksum == [0; 16]
+    pub(crate) fn is_empty_cell(&self, width: ChecksumWidth) -> bool {
+        self.count == 0 && self.checksum[..width.bytes()].iter().all(|&b| b == 0)
     }
 }
 
-impl<T> Symbol<T> {
-    pub(crate) fn encode_count(&mut self, i: u64, n: usize) {
-        let p = libm::ceil(crate::index::p(i as f64) * (n as f64)) as i64;
+/// The number of checksum bytes that are checked when testing if a cell holds a single entry.
+///
+/// Smaller checksums take less bandwidth on the wire, but a cell that holds many entries
+/// is mistaken for a pure cell with a probability of about `2^(-8 * width)` on each test.
+/// Each decoded entry is peeled from every cell it maps to, and each of those cells is
+/// tested again, so decoding a difference of `d` entries over `m` cells performs on the
+/// order of `d * log(m)` tests.
+///
+/// A false positive peels an entry that was never in either set. This usually makes
+/// decoding fail later with [`DecodeError::IndexMismatch`](crate::DecodeError::IndexMismatch)
+/// or [`DecodeError::DuplicateEntry`](crate::DecodeError::DuplicateEntry), aborting a
+/// sync that would otherwise have succeeded. More rarely, it corrupts the decoded difference.
+///
+/// * [`ChecksumWidth::Bytes4`]: about 1 in 4 billion per test. Only suitable for small differences.
+/// * [`ChecksumWidth::Bytes8`]: about 1 in 10^19 per test.
+/// * [`ChecksumWidth::Bytes16`]: negligible. This is the default.
+/// * [`ChecksumWidth::Bytes32`]: also negligible for peers that search for colliding entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumWidth {
+    Bytes4,
+    Bytes8,
+    #[default]
+    Bytes16,
+    Bytes32,
+}
+
+impl ChecksumWidth {
+    /// The number of checksum bytes.
+    pub const fn bytes(self) -> usize {
+        match self {
+            ChecksumWidth::Bytes4 => 4,
+            ChecksumWidth::Bytes8 => 8,
+            ChecksumWidth::Bytes16 => 16,
+            ChecksumWidth::Bytes32 => 32,
+        }
+    }
+
+    /// The checksum width with the given number of bytes, if supported.
+    pub const fn from_bytes(bytes: usize) -> Option<Self> {
+        match bytes {
+            4 => Some(ChecksumWidth::Bytes4),
+            8 => Some(ChecksumWidth::Bytes8),
+            16 => Some(ChecksumWidth::Bytes16),
+            32 => Some(ChecksumWidth::Bytes32),
+            _ => None,
+        }
+    }
+}
+
+impl<T, const W: usize> Symbol<T, W> {
+    /// The checksum width of this symbol type. Unsupported widths fail to compile.
+    pub(crate) const WIDTH: ChecksumWidth = match ChecksumWidth::from_bytes(W) {
+        Some(width) => width,
+        None => panic!("unsupported checksum width"),
+    };
+
+    pub(crate) fn encode_count(&mut self, i: u64, n: i64) {
+        let p = crate::index::expected_count(i, n);
         let d = p - self.count.get();
         self.count.set(d);
     }
 
-    pub(crate) fn decode_count(&mut self, i: usize, n: u64) {
-        let p = libm::ceil(crate::index::p(i as f64) * (n as f64)) as i64;
+    pub(crate) fn decode_count(&mut self, i: u64, n: i64) {
+        let p = crate::index::expected_count(i, n);
         let d = p - self.count.get();
         self.count.set(d);
     }
@@ -0,0 +1,583 @@
+//! A standard wire format for streams of [`Symbol`]s.
+//!
+//! A stream starts with a [`Header`], followed by any number of symbols.
+//! All integers are little endian.
+//!
+//! | field            | size | description                                   |
+//! |------------------|------|-----------------------------------------------|
+//! | `magic`          | 4    | always `b"RIBL"`                              |
+//! | `version`        | 1    | currently `1`                                 |
+//! | `hash`           | 1    | the [`SymbolHasher::scheme`]                  |
+//! | `checksum_width` | 1    | `4`, `8`, `16` or `32` checksum bytes         |
+//! | `flags`          | 1    | bit 0 is set for varint counts                |
+//! | `item_width`     | 4    | the number of bytes in each item              |
+//! | `set_size`       | 8    | the number of entries in the encoded set      |
+//! | `key_id`         | 16   | identifies the hash key without revealing it  |
+//!
+//! Each symbol is then encoded as
+//!
+//! | field      | size             |
+//! |------------|------------------|
+//! | `sum`      | `item_width`     |
+//! | `checksum` | `checksum_width` |
+//! | `count`    | 8, or 1 to 10    |
+//!
+//! When the varint flag is set, `count` is zig-zag encoded and written as a LEB128 varint.
+//! Counts are already delta encoded by the encoder, so they are usually small.
+//!
+//! Streams written with [`Writer::compact`] can use truncated checksums.
+//! See [`ChecksumWidth`] for the trade-offs.
+//!
+//! Only fixed size items are supported.
+
+use core::fmt;
+use std::io::{self, Read, Write};
+
+use zerocopy::{little_endian, FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+use crate::{ChecksumWidth, Decoder, EncoderIter, Symbol, SymbolHasher};
+
+/// The magic bytes at the start of every symbol stream.
+pub const MAGIC: [u8; 4] = *b"RIBL";
+/// The current version of the wire format.
+pub const VERSION: u8 = 1;
+
+pub use crate::hasher::{HASH_BLAKE3, HASH_BLAKE3_KEYED, HASH_SIPHASH, HASH_XXH3};
+
+/// Set in [`Header::flags`] when counts are encoded as varints.
+pub const FLAG_VARINT_COUNT: u8 = 1;
+
+/// The header at the start of every symbol stream.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned,
+)]
+#[repr(C)]
+pub struct Header {
+    pub magic: [u8; 4],
+    pub version: u8,
+    pub hash: u8,
+    pub checksum_width: u8,
+    pub flags: u8,
+    pub item_width: little_endian::U32,
+    pub set_size: little_endian::U64,
+    pub key_id: [u8; 16],
+}
+
+impl Header {
+    /// The header for the symbols produced by `encoder`.
+    pub fn new<T, H: SymbolHasher, const W: usize>(encoder: &EncoderIter<T, H, W>) -> Self {
+        Self {
+            magic: MAGIC,
+            version: VERSION,
+            hash: encoder.hasher.scheme(),
+            checksum_width: W as u8,
+            flags: 0,
+            item_width: little_endian::U32::new(core::mem::size_of::<T>() as u32),
+            set_size: little_endian::U64::new(encoder.entries.len() as u64),
+            key_id: encoder.key_id(),
+        }
+    }
+
+    /// Checks that the symbols following this header can be decoded by `decoder`.
+    pub fn check<T, H: SymbolHasher, const W: usize>(
+        &self,
+        decoder: &Decoder<T, H, W>,
+    ) -> Result<(), WireError> {
+        if self.magic != MAGIC {
+            return Err(WireError::BadMagic);
+        }
+        if self.version != VERSION {
+            return Err(WireError::UnsupportedVersion(self.version));
+        }
+        let width = self.check_format::<T, W>()?;
+        if width.bytes() < decoder.checksum_width().bytes() {
+            return Err(WireError::ChecksumTooNarrow {
+                expected: decoder.checksum_width(),
+                actual: width,
+            });
+        }
+        if self.hash != decoder.hasher.scheme() || self.key_id != decoder.key_id() {
+            return Err(WireError::HashMismatch);
+        }
+        Ok(())
+    }
+
+    /// The header for the symbols produced by `encoder`, with varint counts
+    /// and checksums truncated to `width`.
+    ///
+    /// Fails if `width` is wider than the checksums of the encoder.
+    pub fn compact<T, H: SymbolHasher, const W: usize>(
+        encoder: &EncoderIter<T, H, W>,
+        width: ChecksumWidth,
+    ) -> Result<Self, WireError> {
+        if width.bytes() > W {
+            return Err(WireError::UnsupportedChecksumWidth(width.bytes() as u8));
+        }
+        Ok(Self {
+            checksum_width: width.bytes() as u8,
+            flags: FLAG_VARINT_COUNT,
+            ..Self::new(encoder)
+        })
+    }
+
+    /// Checks that symbols of `T` with `W` checksum bytes can be read in the format
+    /// described by this header, returning the checksum width of the stream.
+    pub(crate) fn check_format<T, const W: usize>(&self) -> Result<ChecksumWidth, WireError> {
+        if self.flags & !FLAG_VARINT_COUNT != 0 {
+            return Err(WireError::UnsupportedFlags(self.flags));
+        }
+        let Some(width) = ChecksumWidth::from_bytes(self.checksum_width as usize)
+            .filter(|width| width.bytes() <= W)
+        else {
+            return Err(WireError::UnsupportedChecksumWidth(self.checksum_width));
+        };
+        let item_width = core::mem::size_of::<T>();
+        if self.item_width.get() as usize != item_width {
+            return Err(WireError::ItemWidthMismatch {
+                expected: item_width,
+                actual: self.item_width.get() as usize,
+            });
+        }
+        Ok(width)
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.checksum_width as usize
+    }
+
+    pub(crate) fn varint_count(&self) -> bool {
+        self.flags & FLAG_VARINT_COUNT != 0
+    }
+}
+
+/// A symbol stream is incompatible with the local decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// The stream does not start with [`MAGIC`].
+    BadMagic,
+    /// The stream uses a version of the wire format that is not supported.
+    UnsupportedVersion(u8),
+    /// The stream uses a checksum width that is not supported.
+    UnsupportedChecksumWidth(u8),
+    /// The stream uses flags that are not supported.
+    UnsupportedFlags(u8),
+    /// The stream's checksums are narrower than the decoder checks.
+    ChecksumTooNarrow {
+        expected: ChecksumWidth,
+        actual: ChecksumWidth,
+    },
+    /// The stream encodes items of a different size.
+    ItemWidthMismatch { expected: usize, actual: usize },
+    /// The stream uses a different hash scheme or key.
+    HashMismatch,
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::BadMagic => f.write_str("not a symbol stream"),
+            WireError::UnsupportedVersion(v) => write!(f, "unsupported version {v}"),
+            WireError::UnsupportedChecksumWidth(w) => {
+                write!(f, "unsupported checksum width {w}")
+            }
+            WireError::UnsupportedFlags(flags) => write!(f, "unsupported flags {flags:#04x}"),
+            WireError::ChecksumTooNarrow { expected, actual } => write!(
+                f,
+                "expected checksums of at least {} bytes, got {} bytes",
+                expected.bytes(),
+                actual.bytes()
+            ),
+            WireError::ItemWidthMismatch { expected, actual } => {
+                write!(f, "expected items of {expected} bytes, got {actual} bytes")
+            }
+            WireError::HashMismatch => f.write_str("hash scheme or key mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+impl From<WireError> for io::Error {
+    fn from(e: WireError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Writes a symbol stream.
+pub struct Writer<O, const W: usize = 16> {
+    inner: O,
+    header: Header,
+}
+
+impl<O: Write, const W: usize> Writer<O, W> {
+    /// Writes the header for the symbols produced by `encoder`.
+    pub fn new<T, H: SymbolHasher>(inner: O, encoder: &EncoderIter<T, H, W>) -> io::Result<Self> {
+        Self::with_header(inner, Header::new(encoder))
+    }
+
+    /// Writes the header for the symbols produced by `encoder`, using varint counts
+    /// and checksums truncated to `width`.
+    pub fn compact<T, H: SymbolHasher>(
+        inner: O,
+        encoder: &EncoderIter<T, H, W>,
+        width: ChecksumWidth,
+    ) -> io::Result<Self> {
+        Self::with_header(inner, Header::compact(encoder, width)?)
+    }
+
+    fn with_header(mut inner: O, header: Header) -> io::Result<Self> {
+        inner.write_all(header.as_bytes())?;
+        Ok(Self { inner, header })
+    }
+
+    /// Writes the next symbol.
+    pub fn write_symbol<T: IntoBytes + Immutable>(
+        &mut self,
+        symbol: &Symbol<T, W>,
+    ) -> io::Result<()> {
+        encode_symbol(&self.header, symbol, |b| self.inner.write_all(b))
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> O {
+        self.inner
+    }
+}
+
+/// Reads a symbol stream.
+pub struct Reader<R, T, const W: usize = 16> {
+    inner: R,
+    header: Header,
+    _item: core::marker::PhantomData<T>,
+}
+
+impl<R: Read, T: FromBytes + IntoBytes + Immutable, const W: usize> Reader<R, T, W> {
+    /// Reads the header, and checks that the symbols can be decoded by `decoder`.
+    pub fn new<H: SymbolHasher>(mut inner: R, decoder: &Decoder<T, H, W>) -> io::Result<Self> {
+        let mut header = Header::new_zeroed();
+        inner.read_exact(header.as_mut_bytes())?;
+        header.check(decoder)?;
+
+        Ok(Self {
+            inner,
+            header,
+            _item: core::marker::PhantomData,
+        })
+    }
+
+    /// The header of this stream.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Reads the next symbol, or `None` if the stream has ended.
+    pub fn read_symbol(&mut self) -> io::Result<Option<Symbol<T, W>>> {
+        let mut symbol = Symbol::<T, W>::default();
+
+        // only a clean end of stream before a symbol is allowed.
+        let sum = symbol.sum.as_mut_bytes();
+        let mut read = 0;
+        while read < sum.len() {
+            match self.inner.read(&mut sum[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.inner
+            .read_exact(&mut symbol.checksum[..self.header.width()])?;
+        if self.header.varint_count() {
+            symbol.count.set(unzigzag(self.read_varint()?));
+        } else {
+            self.inner.read_exact(symbol.count.as_mut_bytes())?;
+        }
+        Ok(Some(symbol))
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut x = 0;
+        for shift in (0..64).step_by(7) {
+            let mut b = [0];
+            self.inner.read_exact(&mut b)?;
+            // the 10th byte only has room for the top bit.
+            if shift == 63 && b[0] > 1 {
+                return Err(varint_overflow());
+            }
+            x |= u64::from(b[0] & 0x7f) << shift;
+            if b[0] & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varint too long",
+        ))
+    }
+}
+
+fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+fn unzigzag(x: u64) -> i64 {
+    ((x >> 1) as i64) ^ -((x & 1) as i64)
+}
+
+/// Encodes `symbol` in the format described by `header`, passing the bytes to `write`.
+pub(crate) fn encode_symbol<T: IntoBytes + Immutable, E, const W: usize>(
+    header: &Header,
+    symbol: &Symbol<T, W>,
+    mut write: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    write(symbol.sum.as_bytes())?;
+    write(&symbol.checksum[..header.width()])?;
+    if header.varint_count() {
+        let mut buf = [0; 10];
+        let len = write_varint(&mut buf, zigzag(symbol.count.get()));
+        write(&buf[..len])
+    } else {
+        write(symbol.count.as_bytes())
+    }
+}
+
+/// Decodes a symbol in the format described by `header` from the start of `buf`,
+/// returning the symbol and the number of bytes it used.
+///
+/// Returns `Ok(None)` if `buf` does not yet hold a whole symbol.
+#[cfg(feature = "tokio")]
+pub(crate) fn decode_symbol<T: FromBytes + IntoBytes + Immutable, const W: usize>(
+    header: &Header,
+    buf: &[u8],
+) -> io::Result<Option<(Symbol<T, W>, usize)>> {
+    let mut symbol = Symbol::<T, W>::default();
+    let sum_len = core::mem::size_of::<T>();
+    let len = sum_len + header.width();
+
+    let Some(fixed) = buf.get(..len) else {
+        return Ok(None);
+    };
+    symbol.sum.as_mut_bytes().copy_from_slice(&fixed[..sum_len]);
+    symbol.checksum[..header.width()].copy_from_slice(&fixed[sum_len..]);
+
+    let count_len = if header.varint_count() {
+        let Some((count, count_len)) = decode_varint(&buf[len..])? else {
+            return Ok(None);
+        };
+        symbol.count.set(unzigzag(count));
+        count_len
+    } else {
+        let Some(count) = buf.get(len..len + 8) else {
+            return Ok(None);
+        };
+        symbol.count.as_mut_bytes().copy_from_slice(count);
+        8
+    };
+
+    Ok(Some((symbol, len + count_len)))
+}
+
+/// Decodes a LEB128 varint from the start of `buf`, returning the value and its length.
+///
+/// Returns `Ok(None)` if `buf` ends before the varint does.
+#[cfg(feature = "tokio")]
+fn decode_varint(buf: &[u8]) -> io::Result<Option<(u64, usize)>> {
+    let mut x = 0;
+    for (i, &b) in buf.iter().take(10).enumerate() {
+        if i == 9 && b > 1 {
+            return Err(varint_overflow());
+        }
+        x |= u64::from(b & 0x7f) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok(Some((x, i + 1)));
+        }
+    }
+    if buf.len() >= 10 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varint too long",
+        ));
+    }
+    Ok(None)
+}
+
+fn varint_overflow() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "varint overflows u64")
+}
+
+/// Writes `x` as a LEB128 varint, returning the number of bytes written.
+fn write_varint(buf: &mut [u8; 10], mut x: u64) -> usize {
+    let mut len = 0;
+    while x >= 0x80 {
+        buf[len] = (x as u8) | 0x80;
+        x >>= 7;
+        len += 1;
+    }
+    buf[len] = x as u8;
+    len + 1
+}
+
+impl<R: Read, T: FromBytes + IntoBytes + Immutable, const W: usize> Iterator for Reader<R, T, W> {
+    type Item = io::Result<Symbol<T, W>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_symbol().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use zerocopy::FromZeros;
+
+    use super::{unzigzag, write_varint, zigzag, Header, Reader, WireError, Writer};
+    use crate::{tests::decode, Blake3, ChecksumWidth, Decoder, Encoder, Symbol};
+
+    /// Decodes the symbols read by `reader` against the `local` symbols.
+    fn decode_stream<const W: usize>(
+        decoder: Decoder<u64, Blake3, W>,
+        reader: Reader<&[u8], u64, W>,
+        local: impl IntoIterator<Item = Symbol<u64, W>>,
+    ) -> (Vec<u64>, Vec<u64>) {
+        decode(decoder, core::iter::zip(reader, local), |d, (r, l)| {
+            d.try_push(r.unwrap(), l)
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut remote = Encoder::default();
+        remote.extend([1u64, 2, 3, 4]);
+        let mut remote = remote.into_iter();
+
+        let mut writer = Writer::new(Vec::new(), &remote).unwrap();
+        for symbol in remote.by_ref().take(10) {
+            writer.write_symbol(&symbol).unwrap();
+        }
+        let buf = writer.into_inner();
+        assert_eq!(buf.len(), 36 + 10 * (8 + 16 + 8));
+
+        let mut local = Encoder::default();
+        local.extend([1u64, 2, 3, 5]);
+
+        let decoder = Decoder::default();
+        let reader = Reader::new(&buf[..], &decoder).unwrap();
+        assert_eq!(reader.header().set_size.get(), 4);
+
+        assert_eq!(decode_stream(decoder, reader, local), (vec![4], vec![5]));
+    }
+
+    #[test]
+    fn reject_incompatible() {
+        let remote = Encoder::<u64>::default().into_iter();
+        let header = Header::new(&remote);
+
+        assert_eq!(header.check(&Decoder::<u64>::default()), Ok(()));
+        assert_eq!(
+            header.check(&Decoder::<u32>::default()),
+            Err(WireError::ItemWidthMismatch {
+                expected: 4,
+                actual: 8
+            })
+        );
+        assert_eq!(
+            header.check(&Decoder::<u64>::with_key([1; 32])),
+            Err(WireError::HashMismatch)
+        );
+
+        let mut bad = header;
+        bad.version = 2;
+        assert_eq!(
+            bad.check(&Decoder::<u64>::default()),
+            Err(WireError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let remote = Encoder::from_iter(0..1000u64).into_iter();
+        let local = Encoder::from_iter(3..1003u64).into_iter();
+
+        let mut writer = Writer::compact(Vec::new(), &remote, ChecksumWidth::Bytes4).unwrap();
+        for symbol in remote.take(100) {
+            writer.write_symbol(&symbol).unwrap();
+        }
+        let buf = writer.into_inner();
+        assert!(buf.len() < 36 + 100 * (8 + 4 + 2));
+
+        let mut decoder = Decoder::default();
+        assert!(Reader::new(&buf[..], &decoder).is_err());
+
+        decoder.set_checksum_width(ChecksumWidth::Bytes4);
+        let reader = Reader::new(&buf[..], &decoder).unwrap();
+        assert_eq!(
+            decode_stream(decoder, reader, local),
+            (vec![0, 1, 2], vec![1000, 1001, 1002])
+        );
+    }
+
+    #[test]
+    fn narrow_checksums() {
+        let remote = Encoder::<u64, _, 8>::with_hasher(Blake3::default()).into_iter();
+        assert!(Writer::compact(Vec::new(), &remote, ChecksumWidth::Bytes32).is_err());
+        assert_eq!(
+            Header::compact(&remote, ChecksumWidth::Bytes16),
+            Err(WireError::UnsupportedChecksumWidth(16))
+        );
+
+        let mut remote = Encoder::<u64, _, 8>::with_hasher(Blake3::default());
+        remote.extend([1, 2, 3]);
+        let mut local = Encoder::<u64, _, 8>::with_hasher(Blake3::default());
+        local.extend([1, 2, 4]);
+        let remote = remote.into_iter();
+
+        let mut writer = Writer::compact(Vec::new(), &remote, ChecksumWidth::Bytes4).unwrap();
+        for symbol in remote.take(10) {
+            writer.write_symbol(&symbol).unwrap();
+        }
+        let buf = writer.into_inner();
+
+        let mut decoder = Decoder::<u64, _, 8>::with_hasher(Blake3::default());
+        decoder.set_checksum_width(ChecksumWidth::Bytes4);
+        let reader = Reader::new(&buf[..], &decoder).unwrap();
+        assert_eq!(decode_stream(decoder, reader, local), (vec![3], vec![4]));
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let mut max = [0; 10];
+        assert_eq!(write_varint(&mut max, u64::MAX), 10);
+        assert_eq!(max[9], 1);
+        let mut overflow = max;
+        overflow[9] = 2;
+
+        let read = |buf: &[u8]| {
+            let mut reader = Reader::<_, u64> {
+                inner: buf,
+                header: Header::new_zeroed(),
+                _item: core::marker::PhantomData,
+            };
+            reader.read_varint()
+        };
+        assert_eq!(read(&max).unwrap(), u64::MAX);
+        assert!(read(&overflow).is_err());
+
+        #[cfg(feature = "tokio")]
+        {
+            assert_eq!(super::decode_varint(&max).unwrap(), Some((u64::MAX, 10)));
+            assert!(super::decode_varint(&overflow).is_err());
+        }
+    }
+
+    #[test]
+    fn zigzag_round_trip() {
+        for x in [0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(x)), x);
+        }
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+    }
+}